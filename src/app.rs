@@ -1,16 +1,108 @@
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::execute;
+use encoding_rs::Encoding;
 use ratatui::DefaultTerminal;
 use core::fmt;
+use std::collections::{HashMap, HashSet};
 use std::io;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use crate::ui::UI;
-use crate::state::{AppState, Direction, Pane};
+use crate::commit_filter::CommitFilter;
+use crate::context::Expansion;
+use crate::diff_settings::DiffSettings;
+use crate::editor;
+use crate::export::{self, ExportFormat};
+use crate::file_tree::FileTree;
+use crate::highlight;
+use crate::search::{Search, SearchScope};
+use crate::state::{AppState, Change, Commit, CommitKind, Direction, FilterStatus, MergeDiff, Pane, Prompt, PromptKind};
 use crate::repo::{Repo, RepoError};
+use crate::review::Review;
+use crate::signature::{SignatureStatus, SignatureVerifier};
+use crate::verdict::VerdictKind;
+use crate::viewed::ViewedMarks;
+use crate::worker::{DiffRequest, DiffResult, DiffWorker, Disconnected, FilterResult, FilterWorker, PathspecResult, PathspecWorker, SignatureResult, SignatureWorker};
+use crate::Args;
+
+const POLL_IDLE: Duration = Duration::from_millis(250);
+const POLL_LOADING: Duration = Duration::from_millis(80);
 
 #[derive(Debug)]
 pub struct App {
     state: AppState,
+    // used on the UI thread for quick lookups, such as the file contents context is expanded from
     repository: Repo,
+    worker: DiffWorker,
+    // requests sent to the worker that haven't come back yet
+    pending: HashSet<DiffRequest>,
+    signatures: SignatureWorker,
+    // commits whose signature hasn't been verified yet
+    unverified: usize,
+    // only run when paths are given, to find the commits they leave with nothing to show
+    pathspec_checks: Option<PathspecWorker>,
+    unchecked: usize,
+    // the background half of the commit filter, kept once finished so it can be reapplied
+    filter_search: Option<FilterSearch>,
+    // number of commits either side of the selected one to compute diffs for ahead of time
+    prefetch: usize,
+    fallback_encoding: &'static Encoding,
+    // from the command line, for workers started after the app
+    pathspecs: Vec<String>,
+    // a file to open in the editor, which has to wait until the terminal can be handed over
+    editor_target: Option<(PathBuf, u32)>,
+}
+
+#[derive(Debug)]
+struct FilterSearch {
+    worker: FilterWorker,
+    searched: usize,
+    total: usize,
+    matched: HashSet<String>,
+}
+
+// the commits listed for the arguments given, with the pseudo-commits first
+pub fn load_commits(repository: &Repo, args: &Args) -> Result<(HashMap<String, Commit>, Vec<String>), AppError> {
+    let mut commits = HashMap::new();
+    let mut commits_order = Vec::new();
+
+    let mut pseudo_commits = Vec::new();
+
+    if args.staged {
+        pseudo_commits.push(repository.staged_changes());
+    }
+
+    if args.unstaged {
+        pseudo_commits.push(repository.unstaged_changes());
+    }
+
+    if let (Some(from_branch), Some(into_branch)) = (&args.from, &args.into) {
+        let (range_commits, range_order) = repository.commits_in_range(
+            into_branch.as_str(),
+            from_branch.as_str(),
+        )?;
+
+        if args.merge_base {
+            pseudo_commits.push(repository.whole_branch(into_branch.as_str(), from_branch.as_str())?);
+        }
+
+        commits = range_commits;
+        commits_order = range_order;
+    }
+
+    for commit in pseudo_commits.into_iter().rev() {
+        commits_order.insert(0, commit.hash.clone());
+        commits.insert(commit.hash.clone(), commit);
+    }
+
+    if commits.is_empty() {
+        return Err(AppError::NoCommits)
+    }
+
+    Ok((commits, commits_order))
 }
 
 #[derive(Debug)]
@@ -20,59 +112,806 @@ pub enum AppError {
 }
 
 impl App {
-    pub fn new(repository: Repo, from_branch: String, into_branch: String) -> Result<Self, AppError> {
-        let (commits, commits_order) = repository.commits_in_range(
-            into_branch.as_str(),
-            from_branch.as_str(),
-        )?;
+    pub fn new(mut repository: Repo, args: Args) -> Result<Self, AppError> {
+        let mut diff_settings = DiffSettings::from_config(&repository.config()?);
+        args.apply_diff_settings(&mut diff_settings);
+        repository.set_diff_settings(diff_settings);
+
+        let (mut commits, commits_order) = load_commits(&repository, &args)?;
 
-        if commits.is_empty() {
-            return Err(AppError::NoCommits)
+        match repository.verdicts() {
+            Ok(verdicts) => {
+                for (hash, verdicts) in verdicts {
+                    if let Some(commit) = commits.get_mut(&hash) {
+                        commit.verdicts = verdicts;
+                    }
+                }
+            },
+            Err(e) => tracing::error!("failed to read verdicts: {e}"),
         }
 
+        let worker = DiffWorker::spawn(args.fallback_encoding, args.pathspecs());
+        highlight::preload();
+
+        let mut verifier = SignatureVerifier::from_config(&repository.config()?);
+        args.apply_signature_settings(&mut verifier);
+
+        let signed_hashes: Vec<String> = commits_order
+            .iter()
+            .filter(|hash| matches!(commits[*hash].kind, CommitKind::Commit))
+            .cloned()
+            .collect();
+
+        let unverified = signed_hashes.len();
+        let pathspecs = args.pathspecs();
+
+        let (pathspec_checks, unchecked) = match pathspecs.is_empty() {
+            true => (None, 0),
+            false => (Some(PathspecWorker::spawn(args.fallback_encoding, pathspecs.clone(), signed_hashes.clone())), signed_hashes.len()),
+        };
+
+        let signatures = SignatureWorker::spawn(args.fallback_encoding, verifier, signed_hashes);
+
+        let mut viewed = ViewedMarks::load(repository.git_dir()).unwrap_or_else(|e| {
+            tracing::error!("failed to read viewed marks: {e}");
+            ViewedMarks::new(repository.git_dir())
+        });
+
+        viewed.set_listed(commits.keys().cloned());
+
+        let review = Review::load(repository.git_dir()).unwrap_or_else(|e| {
+            tracing::error!("failed to read review comments: {e}");
+            Review::new(repository.git_dir())
+        });
+
         let state = AppState::new(
-            from_branch.clone(),
-            into_branch.clone(),
+            args.from,
+            args.into,
             commits,
             commits_order,
+            diff_settings,
+            viewed,
+            review,
         );
 
-        Ok(App { state, repository })
+        Ok(App {
+            state,
+            repository,
+            worker,
+            pending: HashSet::new(),
+            signatures,
+            unverified,
+            pathspec_checks,
+            unchecked,
+            filter_search: None,
+            prefetch: args.prefetch,
+            fallback_encoding: args.fallback_encoding,
+            pathspecs,
+            editor_target: None,
+        })
     }
 
     pub fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
-        while self.state.exit == false {
+        while !self.state.exit {
+            self.request_diffs();
+
             let mut ui = UI::default();
 
             terminal.draw(|frame| ui.render(frame, &mut self.state))?;
 
-            match event::read()? {
-                Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
-                    self.handle_key_event(key_event)
+            // wake up regularly while work is pending to pick up results and animate the spinner
+            let timeout = if self.is_busy() { POLL_LOADING } else { POLL_IDLE };
+
+            if event::poll(timeout)? {
+                match event::read()? {
+                    Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                        self.handle_key_event(key_event)
+                    }
+                    _ => {}
+                }
+            }
+
+            if let Some((path, line)) = self.editor_target.take() {
+                self.run_editor(terminal, path.as_path(), line)?;
+            }
+
+            loop {
+                match self.worker.try_recv() {
+                    Ok(Some(result)) => self.handle_diff_result(result),
+                    Ok(None) => break,
+                    Err(disconnected) => {
+                        self.handle_diff_worker_stopped(disconnected);
+                        break;
+                    },
                 }
-                _ => {}
             }
+
+            loop {
+                match self.signatures.try_recv() {
+                    Ok(Some(result)) => self.handle_signature_result(result),
+                    Ok(None) => break,
+                    Err(Disconnected(reason)) => {
+                        if self.unverified > 0 {
+                            self.unverified = 0;
+                            self.worker_stopped("signature checks", &reason);
+                        }
+                        break;
+                    },
+                }
+            }
+
+            while let Some(pathspec_checks) = &mut self.pathspec_checks {
+                match pathspec_checks.try_recv() {
+                    Ok(Some(result)) => self.handle_pathspec_result(result),
+                    Ok(None) => break,
+                    Err(Disconnected(reason)) => {
+                        if self.unchecked > 0 {
+                            self.unchecked = 0;
+                            self.worker_stopped("path checks", &reason);
+                        }
+                        break;
+                    },
+                }
+            }
+
+            self.handle_filter_results();
+
+            self.state.pending_diffs = self.pending.len();
+            self.state.tick = self.state.tick.wrapping_add(1);
         }
         Ok(())
     }
 
     fn handle_key_event(&mut self, key_event: KeyEvent) {
+        if self.state.show_commit_details {
+            match key_event.code {
+                KeyCode::Char('q') => self.state.exit(),
+                KeyCode::Char('i') | KeyCode::Esc | KeyCode::Enter => self.state.show_commit_details = false,
+                _ => {},
+            }
+            return;
+        }
+
+        self.state.notice = None;
+
+        if self.state.prompt.is_some() {
+            self.handle_prompt_key(key_event);
+            return;
+        }
+
         match key_event.code {
             KeyCode::Char('q') => self.state.exit(),
             KeyCode::Char('j') | KeyCode::Down => self.state.navigate(Direction::Down),
-            KeyCode::Char('J') => {
-                if matches!(self.state.selected_pane, Pane::Diff) {
-                    self.state.jump_hunk(Direction::Down)
-                }
+            KeyCode::Char('J') if self.state.selected_pane == Pane::Diff => self.state.jump_hunk(Direction::Down),
+            KeyCode::Char('K') if self.state.selected_pane == Pane::Diff => self.state.jump_hunk(Direction::Up),
+            KeyCode::Char(']') if self.state.selected_pane == Pane::Diff => self.state.jump_file(Direction::Down),
+            KeyCode::Char('[') if self.state.selected_pane == Pane::Diff => self.state.jump_file(Direction::Up),
+            KeyCode::Char('G') if self.state.selected_pane == Pane::Diff => self.state.jump_to_end(Direction::Down),
+            KeyCode::Char('g') if self.state.selected_pane == Pane::Diff => self.state.jump_to_end(Direction::Up),
+            KeyCode::Char('{') if self.state.selected_pane == Pane::Diff => self.expand_context(Expansion::Above),
+            KeyCode::Char('}') if self.state.selected_pane == Pane::Diff => self.expand_context(Expansion::Below),
+            KeyCode::Char('E') if self.state.selected_pane == Pane::Diff => self.expand_context(Expansion::Full),
+            KeyCode::Char('/') if self.state.selected_pane == Pane::Diff => self.open_search(),
+            KeyCode::Char('/') if self.state.selected_pane == Pane::Commits => self.open_filter(),
+            KeyCode::Char('n') => self.state.jump_match(Direction::Down),
+            KeyCode::Char('N') => self.state.jump_match(Direction::Up),
+            KeyCode::Esc if self.state.selected_pane == Pane::Commits => self.clear_filter(),
+            KeyCode::Esc => {
+                self.state.search = None;
+                self.state.range_start = None;
             },
             KeyCode::Char('k') | KeyCode::Up => self.state.navigate(Direction::Up),
             KeyCode::Char('c') => self.state.select_pane(Pane::Commits),
             KeyCode::Char('d') => self.state.select_pane(Pane::Diff),
             KeyCode::Char('f') => self.state.select_pane(Pane::Files),
+            KeyCode::Char('m') => self.cycle_merge_diff(),
+            KeyCode::Char('s') => self.state.toggle_diff_view(),
+            KeyCode::Char('i') => self.state.show_commit_details = true,
+            KeyCode::Char('v') => self.toggle_viewed(),
+            KeyCode::Char('a') if self.state.selected_pane == Pane::Diff => self.open_comment(),
+            KeyCode::Char('D') if self.state.selected_pane == Pane::Diff => self.delete_comment(),
+            KeyCode::Char('o') if self.state.selected_pane == Pane::Diff => self.open_in_editor(),
+            KeyCode::Char('V') if self.state.selected_pane == Pane::Diff => self.state.toggle_range(),
+            KeyCode::Char('r') if self.state.selected_pane == Pane::Commits => self.open_note(),
+            KeyCode::Char('R') if self.state.selected_pane == Pane::Commits => self.open_verdict(),
+            KeyCode::Char('X') => self.open_export(),
+            KeyCode::Char('+') | KeyCode::Char('=') => self.update_diff_settings(|s| s.context_lines += 1),
+            KeyCode::Char('-') => self.update_diff_settings(|s| s.context_lines = s.context_lines.saturating_sub(1)),
+            KeyCode::Char('w') => self.update_diff_settings(|s| s.ignore_whitespace = !s.ignore_whitespace),
+            KeyCode::Char('W') => self.update_diff_settings(|s| s.ignore_whitespace_change = !s.ignore_whitespace_change),
+            KeyCode::Char('B') => self.update_diff_settings(|s| s.ignore_blank_lines = !s.ignore_blank_lines),
+            KeyCode::Char('A') => self.update_diff_settings(|s| s.algorithm = s.algorithm.next()),
+            KeyCode::Char('I') => self.update_diff_settings(|s| s.indent_heuristic = !s.indent_heuristic),
             KeyCode::Enter => self.state.select(),
             _ => {},
         }
     }
+
+    fn handle_prompt_key(&mut self, key_event: KeyEvent) {
+        let Some(prompt) = &mut self.state.prompt else {
+            return;
+        };
+
+        match key_event.code {
+            KeyCode::Esc => self.cancel_prompt(),
+            KeyCode::Enter => self.confirm_prompt(),
+            KeyCode::Tab => {
+                match &mut prompt.kind {
+                    PromptKind::Search { scope, .. } => {
+                        *scope = scope.next();
+                        self.update_prompt();
+                    },
+                    PromptKind::Verdict { kind } => *kind = VerdictKind::next(*kind),
+                    _ => {},
+                }
+            },
+            KeyCode::Backspace => {
+                prompt.input.pop();
+                self.update_prompt();
+            },
+            KeyCode::Char(c) => {
+                prompt.input.push(c);
+                self.update_prompt();
+            },
+            _ => {},
+        }
+    }
+
+    fn open_search(&mut self) {
+        let scope = self.state.search.as_ref().map_or(SearchScope::All, |search| search.scope);
+
+        self.state.prompt = Some(Prompt {
+            kind: PromptKind::Search { scope, origin: self.state.cursor as i32 },
+            input: String::new(),
+            invalid: false,
+        });
+    }
+
+    fn open_filter(&mut self) {
+        let input = self.state.commit_filter.as_ref().map_or(String::new(), |filter| filter.query().to_string());
+
+        self.state.prompt = Some(Prompt {
+            kind: PromptKind::Filter,
+            input,
+            invalid: false,
+        });
+    }
+
+    // edits the comment under the cursor, or writes a new one on the lines selected
+    fn open_comment(&mut self) {
+        let (editing, input) = match self.state.comment_at_cursor() {
+            Some(idx) => (Some(idx), self.state.review.comments[idx].body.clone()),
+            None if self.state.comment_anchor().is_some() => (None, String::new()),
+            None => return,
+        };
+
+        self.state.prompt = Some(Prompt {
+            kind: PromptKind::Comment { editing },
+            input,
+            invalid: false,
+        });
+    }
+
+    fn open_note(&mut self) {
+        let input = self.state.review
+            .note(self.state.get_selected_commit().hash.as_str())
+            .unwrap_or_default()
+            .to_string();
+
+        self.state.prompt = Some(Prompt { kind: PromptKind::Note, input, invalid: false });
+    }
+
+    // starts from the verdict the reviewer already left on the commit, if there is one
+    fn open_verdict(&mut self) {
+        let commit = self.state.get_selected_commit();
+
+        if commit.kind != CommitKind::Commit {
+            return;
+        }
+
+        let email = match self.repository.reviewer_email() {
+            Ok(email) => email,
+            Err(e) => {
+                self.state.notice = Some(format!("couldn't tell who the reviewer is: {e}"));
+                return;
+            },
+        };
+
+        let (kind, input) = commit.verdicts
+            .iter()
+            .find(|verdict| verdict.email == email)
+            .map_or((Some(VerdictKind::Approved), String::new()), |verdict| (Some(verdict.kind), verdict.note.clone()));
+
+        self.state.prompt = Some(Prompt { kind: PromptKind::Verdict { kind }, input, invalid: false });
+    }
+
+    fn open_export(&mut self) {
+        self.state.prompt = Some(Prompt {
+            kind: PromptKind::Export,
+            input: String::from("review.md"),
+            invalid: false,
+        });
+    }
+
+    fn delete_comment(&mut self) {
+        if let Err(e) = self.state.delete_comment_at_cursor() {
+            tracing::error!("failed to save review comments: {e}");
+        }
+    }
+
+    // applies the prompt's input as it's typed
+    fn update_prompt(&mut self) {
+        let Some(prompt) = &mut self.state.prompt else {
+            return;
+        };
+
+        match prompt.kind {
+            PromptKind::Search { scope, origin } => {
+                if prompt.input.is_empty() {
+                    prompt.invalid = false;
+                    self.state.search = None;
+                    self.state.scroll_to(origin);
+                    return;
+                }
+
+                match Search::new(prompt.input.as_str(), scope) {
+                    Ok(search) => {
+                        prompt.invalid = false;
+                        self.state.search = Some(search);
+                        self.state.jump_first_match_from(origin);
+                    },
+                    // keep the last valid search while the pattern is incomplete
+                    Err(_) => prompt.invalid = true,
+                }
+            },
+            PromptKind::Filter => {
+                // path and `-S` terms are only searched for once the filter is confirmed
+                let filter = CommitFilter::parse(prompt.input.as_str());
+                let match_count = self.state.filter_commits(Some(&filter), None);
+
+                if let Some(prompt) = &mut self.state.prompt {
+                    prompt.invalid = match_count == 0;
+                }
+            },
+            PromptKind::Comment { .. } | PromptKind::Note | PromptKind::Export | PromptKind::Verdict { .. } => {},
+        }
+    }
+
+    fn confirm_prompt(&mut self) {
+        let Some(prompt) = self.state.prompt.take() else {
+            return;
+        };
+
+        match prompt.kind {
+            PromptKind::Search { .. } => {},
+            PromptKind::Filter => self.apply_filter(CommitFilter::parse(prompt.input.as_str())),
+            PromptKind::Comment { editing } => self.save_comment(editing, prompt.input),
+            PromptKind::Note => {
+                if let Err(e) = self.state.set_note(prompt.input) {
+                    tracing::error!("failed to save review notes: {e}");
+                }
+            },
+            PromptKind::Export => self.export_review(PathBuf::from(prompt.input)),
+            PromptKind::Verdict { kind } => self.set_verdict(kind, prompt.input.trim()),
+        }
+    }
+
+    fn set_verdict(&mut self, kind: Option<VerdictKind>, note: &str) {
+        let hash = self.state.get_selected_commit().hash.clone();
+
+        match self.repository.set_verdict(hash.as_str(), kind, note) {
+            Ok(verdicts) => {
+                if let Some(commit) = self.state.commits.get_mut(&hash) {
+                    commit.verdicts = verdicts;
+                }
+            },
+            Err(e) => {
+                tracing::error!("failed to save verdict on {hash}: {e}");
+                self.state.notice = Some(format!("couldn't save the verdict: {e}"));
+            },
+        }
+    }
+
+    // writes the review of every commit, as JSON if the path ends in `.json` and Markdown otherwise
+    fn export_review(&mut self, path: PathBuf) {
+        let title = export::review_title(self.state.from_branch.as_deref(), self.state.into_branch.as_deref());
+
+        let commits: Vec<&Commit> = self.state.unfiltered_order
+            .iter()
+            .map(|hash| &self.state.commits[hash])
+            .collect();
+
+        let written = export::export(&self.repository, title.as_str(), &commits, &self.state.review, ExportFormat::for_path(&path))
+            .map_err(|e| e.to_string())
+            .and_then(|document| fs::write(&path, document).map_err(|e| e.to_string()));
+
+        self.state.notice = Some(match written {
+            Ok(()) => format!("review written to {}", path.display()),
+            Err(e) => format!("couldn't write the review: {e}"),
+        });
+    }
+
+    fn save_comment(&mut self, editing: Option<usize>, body: String) {
+        // an empty comment isn't worth keeping
+        if body.trim().is_empty() {
+            return;
+        }
+
+        let saved = match editing {
+            Some(idx) => self.state.edit_comment(idx, body),
+            None => self.state.add_comment(body),
+        };
+
+        if let Err(e) = saved {
+            tracing::error!("failed to save review comments: {e}");
+        }
+    }
+
+    fn apply_filter(&mut self, filter: CommitFilter) {
+        self.filter_search = None;
+
+        if filter.is_empty() {
+            self.clear_filter();
+            return;
+        }
+
+        let match_count = self.state.filter_commits(Some(&filter), None);
+
+        self.state.filter_status = if match_count == 0 {
+            FilterStatus::NoMatches
+        } else if filter.needs_repository() {
+            let hashes = self.state.commits_order.clone();
+            let total = hashes.len();
+
+            self.filter_search = Some(FilterSearch {
+                worker: FilterWorker::spawn(
+                    self.fallback_encoding,
+                    self.pathspecs.clone(),
+                    self.state.diff_settings,
+                    &filter,
+                    hashes,
+                ),
+                searched: 0,
+                total,
+                matched: HashSet::new(),
+            });
+
+            FilterStatus::Searching { searched: 0, total }
+        } else {
+            FilterStatus::Matched(match_count)
+        };
+
+        self.state.commit_filter = Some(filter);
+    }
+
+    fn clear_filter(&mut self) {
+        self.filter_search = None;
+        self.state.commit_filter = None;
+        self.state.filter_commits(None, None);
+    }
+
+    // lists the commits matching the confirmed filter again, e.g. after editing it was cancelled
+    fn reapply_filter(&mut self) {
+        let filter = self.state.commit_filter.clone();
+
+        let matched = self.filter_search
+            .as_ref()
+            .filter(|search| search.searched == search.total)
+            .map(|search| &search.matched);
+
+        self.state.filter_commits(filter.as_ref(), matched);
+    }
+
+    fn cancel_prompt(&mut self) {
+        let Some(prompt) = self.state.prompt.take() else {
+            return;
+        };
+
+        match prompt.kind {
+            PromptKind::Search { origin, .. } => {
+                self.state.search = None;
+                self.state.scroll_to(origin);
+            },
+            PromptKind::Filter => self.reapply_filter(),
+            PromptKind::Comment { .. } | PromptKind::Note | PromptKind::Export | PromptKind::Verdict { .. } => {},
+        }
+    }
+
+    // loads both versions of the file under the cursor to reveal more of it around the hunk there
+    fn expand_context(&mut self, expansion: Expansion) {
+        let Some((file_idx, file)) = self.state.file_at_cursor() else {
+            return;
+        };
+
+        let (old, new) = file.row(self.state.cursor - file.scroll_start);
+
+        // files shown as a placeholder have no hunks to expand
+        let Some(change_idx) = old.or(new) else {
+            return;
+        };
+
+        let path = file.path.to_string();
+
+        let lines = self.repository
+            .file_lines(file.old_path.unwrap_or(file.path), file.blob_ids.old.as_deref())
+            .and_then(|old_lines| Ok((old_lines, self.repository.file_lines(file.path, file.blob_ids.new.as_deref())?)));
+
+        match lines {
+            Ok((old_lines, new_lines)) => {
+                self.state.expand_context(file_idx, change_idx, expansion, &old_lines, &new_lines);
+            },
+            Err(e) => tracing::error!("failed to read {path} to expand its context: {e}"),
+        }
+    }
+
+    /*
+     * Picks the file and line to edit for the cursor: the working tree file at the cursor's
+     * line on the new side, or the line nearest to it. Files without a new side, and those
+     * no longer in the working tree, are opened as a read-only copy of the version shown.
+     * */
+    fn open_in_editor(&mut self) {
+        let Some((_, file)) = self.state.file_at_cursor() else {
+            return;
+        };
+
+        let (old, new) = file.row(self.state.cursor - file.scroll_start);
+        let change_idx = new.or(old).unwrap_or(0);
+
+        // the first line number at or after the cursor, or failing that the last one before it
+        let nearest_line = |lineno: fn(&Change) -> Option<u32>| {
+            file.changes
+                .iter()
+                .skip(change_idx)
+                .find_map(lineno)
+                .or_else(|| file.changes.iter().take(change_idx).rev().find_map(lineno))
+                .unwrap_or(1)
+        };
+
+        let new_line = nearest_line(|change| change.new_lineno);
+        let old_line = nearest_line(|change| change.old_lineno);
+
+        let target = match (&file.blob_ids.new, &file.blob_ids.old) {
+            (Some(new_blob), _) => self.repository
+                .workdir_file(file.path)
+                .filter(|path| path.exists())
+                .map(Ok)
+                .unwrap_or_else(|| self.read_only_copy(file.path, new_blob))
+                .map(|path| (path, new_line)),
+            (None, Some(old_blob)) => self
+                .read_only_copy(file.old_path.unwrap_or(file.path), old_blob)
+                .map(|path| (path, old_line)),
+            (None, None) => return,
+        };
+
+        match target {
+            Ok(target) => self.editor_target = Some(target),
+            Err(e) => self.state.notice = Some(format!("couldn't open {}: {e}", file.path)),
+        }
+    }
+
+    fn read_only_copy(&self, path: &str, blob_id: &str) -> Result<PathBuf, RepoError> {
+        let content = self.repository.file_content(path, blob_id)?;
+        Ok(editor::read_only_copy(path, &content)?)
+    }
+
+    // hands the terminal over to the editor until it exits
+    fn run_editor(&mut self, terminal: &mut DefaultTerminal, path: &Path, line: u32) -> io::Result<()> {
+        terminal::disable_raw_mode()?;
+        execute!(io::stdout(), LeaveAlternateScreen)?;
+
+        let opened = editor::open(path, line);
+
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        terminal.clear()?;
+
+        if let Err(e) = opened {
+            tracing::error!("failed to run the editor on {}: {e}", path.display());
+            self.state.notice = Some(format!("couldn't run the editor: {e}"));
+        }
+
+        Ok(())
+    }
+
+    fn toggle_viewed(&mut self) {
+        if let Err(e) = self.state.toggle_viewed() {
+            tracing::error!("failed to save viewed marks: {e}");
+        }
+    }
+
+    fn cycle_merge_diff(&mut self) {
+        let commit = self.state.get_selected_commit();
+
+        if !commit.is_merge() {
+            return;
+        }
+
+        self.request_diff(self.state.selected_commit, commit.merge_diff.next(commit.parents.len()));
+    }
+
+    fn update_diff_settings(&mut self, update: impl FnOnce(&mut DiffSettings)) {
+        update(&mut self.state.diff_settings);
+        // the export computes diffs on this thread, which should match the ones shown
+        self.repository.set_diff_settings(self.state.diff_settings);
+    }
+
+    /*
+     * Requests the selected commit's diff first, then those of its neighbours, so the worker
+     * picks them up in that order. Diffs of other commits are computed or rebuilt when they
+     * are next selected or prefetched.
+     * */
+    fn request_diffs(&mut self) {
+        let selected = self.state.selected_commit;
+
+        let neighbours = (1..=self.prefetch)
+            .flat_map(|distance| [selected.checked_sub(distance), Some(selected + distance)])
+            .flatten()
+            .filter(|&index| index < self.state.commits_order.len());
+
+        let indices: Vec<usize> = std::iter::once(selected)
+            .chain(neighbours)
+            .filter(|&index| self.state.get_commit(index).needs_diff(&self.state.diff_settings))
+            .collect();
+
+        for index in indices {
+            self.request_diff(index, self.state.get_commit(index).merge_diff);
+        }
+    }
+
+    fn request_diff(&mut self, index: usize, merge_diff: MergeDiff) {
+        let commit = self.state.get_commit(index);
+
+        let request = DiffRequest {
+            hash: commit.hash.clone(),
+            kind: commit.kind,
+            merge_diff,
+            diff_settings: self.state.diff_settings,
+        };
+
+        if self.pending.insert(request.clone()) {
+            self.worker.request(request);
+        }
+    }
+
+    fn handle_diff_result(&mut self, DiffResult { request, file_tree }: DiffResult) {
+        self.pending.remove(&request);
+
+        let Some(index) = self.state.commits_order.iter().position(|hash| *hash == request.hash) else {
+            return;
+        };
+
+        let file_tree = match file_tree {
+            Ok(file_tree) => file_tree,
+            Err(e) => {
+                // store an empty diff rather than requesting it again on every frame
+                tracing::error!("failed to compute diff for {}: {e}", request.hash);
+                FileTree::new("root")
+            },
+        };
+
+        self.state.set_commit_diff(index, file_tree, request.merge_diff, request.diff_settings);
+    }
+
+    fn handle_filter_results(&mut self) {
+        let Some(search) = &mut self.filter_search else {
+            return;
+        };
+
+        let mut received = false;
+
+        loop {
+            match search.worker.try_recv() {
+                Ok(Some(FilterResult { hash, matched })) => {
+                    received = true;
+                    search.searched += 1;
+
+                    match matched {
+                        Ok(true) => {
+                            search.matched.insert(hash);
+                        },
+                        Ok(false) => {},
+                        Err(e) => tracing::error!("failed to search {hash} for the commit filter: {e}"),
+                    }
+                },
+                Ok(None) => break,
+                Err(Disconnected(reason)) => {
+                    // settle for the matches found so far rather than searching forever
+                    if search.searched < search.total {
+                        let message = format!("commit filter stopped after {} of {} commits", search.searched, search.total);
+                        search.total = search.searched;
+                        received = true;
+
+                        tracing::error!("{message}: {reason}");
+                        self.state.notice = Some(format!("{message}: {reason}"));
+                    }
+                    break;
+                },
+            }
+        }
+
+        if !received {
+            return;
+        }
+
+        if search.searched < search.total {
+            self.state.filter_status = FilterStatus::Searching { searched: search.searched, total: search.total };
+            return;
+        }
+
+        let match_count = search.matched.len();
+
+        self.state.filter_status = if match_count == 0 {
+            FilterStatus::NoMatches
+        } else {
+            FilterStatus::Matched(match_count)
+        };
+
+        // a filter being edited is applied once the prompt closes
+        if self.state.prompt.is_none() {
+            self.reapply_filter();
+        }
+    }
+
+    /*
+     * The diff worker only stops when it couldn't open the repository or panicked, after
+     * which nothing it was asked for will come back. The diffs waiting on it are left empty,
+     * as when computing one fails, so they aren't requested again on every frame.
+     * */
+    fn handle_diff_worker_stopped(&mut self, Disconnected(reason): Disconnected) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        for request in std::mem::take(&mut self.pending) {
+            if let Some(index) = self.state.commits_order.iter().position(|hash| *hash == request.hash) {
+                self.state.set_commit_diff(index, FileTree::new("root"), request.merge_diff, request.diff_settings);
+            }
+        }
+
+        self.worker_stopped("diffs", &reason);
+    }
+
+    fn worker_stopped(&mut self, work: &str, reason: &str) {
+        tracing::error!("{work} stopped: {reason}");
+        self.state.notice = Some(format!("{work} stopped: {reason}"));
+    }
+
+    fn is_busy(&self) -> bool {
+        !self.pending.is_empty()
+            || self.unverified > 0
+            || self.unchecked > 0
+            || self.filter_search.as_ref().is_some_and(|search| search.searched < search.total)
+    }
+
+    fn handle_signature_result(&mut self, SignatureResult { hash, status }: SignatureResult) {
+        self.unverified = self.unverified.saturating_sub(1);
+
+        let status = match status {
+            Ok(status) => status,
+            Err(e) => {
+                // e.g. gpg isn't installed, in which case the signature can't be checked
+                tracing::error!("failed to verify signature of {hash}: {e}");
+                SignatureStatus::Unverifiable(e.to_string())
+            },
+        };
+
+        if let Some(commit) = self.state.commits.get_mut(&hash) {
+            commit.signature = Some(status);
+        }
+    }
+
+    fn handle_pathspec_result(&mut self, PathspecResult { hash, touched }: PathspecResult) {
+        self.unchecked = self.unchecked.saturating_sub(1);
+
+        match touched {
+            Ok(touched) => {
+                if let Some(commit) = self.state.commits.get_mut(&hash) {
+                    commit.outside_paths = !touched;
+                }
+            },
+            Err(e) => tracing::error!("failed to check the paths changed by {hash}: {e}"),
+        }
+    }
 }
 
 impl fmt::Display for AppError {
@@ -0,0 +1,137 @@
+use crate::state::{Commit, CommitKind};
+
+/*
+ * A query narrowing the commit list. Terms are separated by spaces and must all match,
+ * with double quotes grouping words into one term:
+ *
+ *   text           message contains the text, ignoring case
+ *   author:text    author name or email contains the text, ignoring case
+ *   hash:prefix    hash starts with the prefix
+ *   path:text      the commit touches a path containing the text
+ *   -S text        an added or removed line contains the text, like `git log -S`
+ *
+ * Path and `-S` terms need each commit's diff, so they're checked against the repository
+ * rather than the commit's metadata.
+ * */
+#[derive(Debug, Clone)]
+pub struct CommitFilter {
+    query: String,
+    terms: Vec<FilterTerm>,
+}
+
+#[derive(Debug, Clone)]
+enum FilterTerm {
+    Message(String),
+    Author(String),
+    Hash(String),
+    Path(String),
+    Pickaxe(String),
+}
+
+impl CommitFilter {
+    pub fn parse(query: &str) -> Self {
+        let mut terms = Vec::new();
+        let mut words = split_words(query).into_iter();
+
+        while let Some(word) = words.next() {
+            let term = if let Some(author) = word.strip_prefix("author:") {
+                FilterTerm::Author(author.to_lowercase())
+            } else if let Some(hash) = word.strip_prefix("hash:") {
+                FilterTerm::Hash(hash.to_lowercase())
+            } else if let Some(path) = word.strip_prefix("path:") {
+                FilterTerm::Path(path.to_string())
+            } else if let Some(pickaxe) = word.strip_prefix("-S") {
+                // accepts both `-Stext` and `-S text`
+                match pickaxe {
+                    "" => match words.next() {
+                        Some(pickaxe) => FilterTerm::Pickaxe(pickaxe),
+                        None => continue,
+                    },
+                    _ => FilterTerm::Pickaxe(pickaxe.to_string()),
+                }
+            } else {
+                FilterTerm::Message(word.to_lowercase())
+            };
+
+            terms.push(term);
+        }
+
+        CommitFilter { query: query.trim().to_string(), terms }
+    }
+
+    pub fn query(&self) -> &str {
+        self.query.as_str()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn paths(&self) -> Vec<String> {
+        self.terms
+            .iter()
+            .filter_map(|term| match term {
+                FilterTerm::Path(path) => Some(path.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn pickaxes(&self) -> Vec<String> {
+        self.terms
+            .iter()
+            .filter_map(|term| match term {
+                FilterTerm::Pickaxe(pickaxe) => Some(pickaxe.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn needs_repository(&self) -> bool {
+        self.terms.iter().any(|term| matches!(term, FilterTerm::Path(_) | FilterTerm::Pickaxe(_)))
+    }
+
+    // checks the terms that can be answered from the commit's metadata alone
+    pub fn matches_metadata(&self, commit: &Commit) -> bool {
+        // the pseudo-commits have no message, author or hash to match against
+        if !matches!(commit.kind, CommitKind::Commit) {
+            return self.is_empty();
+        }
+
+        let message = commit.message.as_deref().unwrap_or_default().to_lowercase();
+
+        self.terms.iter().all(|term| match term {
+            FilterTerm::Message(text) => message.contains(text.as_str()),
+            FilterTerm::Author(text) => commit.author.as_ref().is_some_and(|author| {
+                author.name.to_lowercase().contains(text.as_str())
+                    || author.email.to_lowercase().contains(text.as_str())
+            }),
+            FilterTerm::Hash(prefix) => commit.hash.starts_with(prefix.as_str()),
+            FilterTerm::Path(_) | FilterTerm::Pickaxe(_) => true,
+        })
+    }
+}
+
+fn split_words(query: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+
+    for c in query.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            },
+            c => word.push(c),
+        }
+    }
+
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
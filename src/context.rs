@@ -0,0 +1,434 @@
+use crate::state::{Change, ChangeKind};
+
+// unchanged lines revealed each time a hunk is expanded upwards or downwards
+const EXPAND_STEP: u32 = 10;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Expansion {
+    Above,
+    Below,
+    // every unchanged line of the file, leaving a single hunk
+    Full,
+}
+
+// a line of a file's old or new version, decoded the same way as the diff's own lines
+#[derive(Debug, Clone)]
+pub struct SourceLine {
+    pub text: String,
+    pub encoding: Option<&'static str>,
+}
+
+#[derive(Debug)]
+struct Hunk {
+    range: HunkRange,
+    // the enclosing function git found for the hunk, including its leading space
+    section: String,
+    lines: Vec<Change>,
+}
+
+// the first line covered on each side, counting from 1, and how many lines are covered
+#[derive(Debug, Clone, Copy)]
+struct HunkRange {
+    old_first: u32,
+    old_lines: u32,
+    new_first: u32,
+    new_lines: u32,
+}
+
+/*
+ * Reveals unchanged lines around the hunk holding the change at `change_idx`, taking them
+ * from the file's old and new versions. Hunks that end up touching are merged, and every
+ * hunk header is rewritten to cover its new range. The changes are left as they are if any
+ * of their headers can't be read.
+ * */
+pub fn expand(changes: &mut Vec<Change>, change_idx: usize, expansion: Expansion, old: &[SourceLine], new: &[SourceLine]) {
+    let hunk_idx = changes
+        .iter()
+        .take(change_idx + 1)
+        .filter(|change| matches!(change.kind, ChangeKind::HunkHeader))
+        .count()
+        .saturating_sub(1);
+
+    let Some(mut hunks) = split_hunks(changes) else {
+        return;
+    };
+
+    match expansion {
+        Expansion::Above => extend_above(&mut hunks, hunk_idx, EXPAND_STEP, old, new),
+        Expansion::Below => extend_below(&mut hunks, hunk_idx, EXPAND_STEP, old, new),
+        Expansion::Full => {
+            for idx in 0..hunks.len() {
+                extend_above(&mut hunks, idx, u32::MAX, old, new);
+                extend_below(&mut hunks, idx, u32::MAX, old, new);
+            }
+        },
+    }
+
+    *changes = merge_touching(hunks)
+        .into_iter()
+        .flat_map(|hunk| {
+            let header = Change {
+                text: format!(
+                    "@@ -{} +{} @@{}",
+                    format_range(hunk.range.old_first, hunk.range.old_lines),
+                    format_range(hunk.range.new_first, hunk.range.new_lines),
+                    hunk.section,
+                ),
+                kind: ChangeKind::HunkHeader,
+                encoding: None,
+                old_lineno: None,
+                new_lineno: None,
+            };
+
+            std::iter::once(header).chain(hunk.lines)
+        })
+        .collect();
+}
+
+// takes the changes apart into hunks, or returns None without touching them
+fn split_hunks(changes: &mut Vec<Change>) -> Option<Vec<Hunk>> {
+    let ranges = changes
+        .iter()
+        .filter(|change| matches!(change.kind, ChangeKind::HunkHeader))
+        .map(|change| parse_header(change.text.as_str()))
+        .collect::<Option<Vec<_>>>()?;
+
+    if !matches!(changes.first()?.kind, ChangeKind::HunkHeader) {
+        return None;
+    }
+
+    let mut ranges = ranges.into_iter();
+    let mut hunks: Vec<Hunk> = Vec::new();
+
+    for change in std::mem::take(changes) {
+        if matches!(change.kind, ChangeKind::HunkHeader) && let Some((range, section)) = ranges.next() {
+            hunks.push(Hunk { range, section, lines: Vec::new() });
+        } else if let Some(hunk) = hunks.last_mut() {
+            hunk.lines.push(change);
+        }
+    }
+
+    Some(hunks)
+}
+
+fn extend_above(hunks: &mut [Hunk], idx: usize, limit: u32, old: &[SourceLine], new: &[SourceLine]) {
+    let (prev_old_end, prev_new_end) = match idx.checked_sub(1) {
+        Some(prev) => (hunks[prev].range.old_end(), hunks[prev].range.new_end()),
+        None => (1, 1),
+    };
+
+    let hunk = &mut hunks[idx];
+    let range = hunk.range;
+
+    let count = range.old_first.saturating_sub(prev_old_end)
+        .min(range.new_first.saturating_sub(prev_new_end))
+        .min(limit);
+
+    let revealed = (1..=count)
+        .rev()
+        .map(|offset| context_line(range.old_first - offset, range.new_first - offset, old, new));
+
+    hunk.lines.splice(0..0, revealed);
+    hunk.range.old_first -= count;
+    hunk.range.new_first -= count;
+    hunk.range.old_lines += count;
+    hunk.range.new_lines += count;
+}
+
+fn extend_below(hunks: &mut [Hunk], idx: usize, limit: u32, old: &[SourceLine], new: &[SourceLine]) {
+    // the last hunk can reach the end of the file, one past its last line
+    let (next_old_first, next_new_first) = match hunks.get(idx + 1) {
+        Some(next) => (next.range.old_first, next.range.new_first),
+        None => (old.len() as u32 + 1, new.len() as u32 + 1),
+    };
+
+    let hunk = &mut hunks[idx];
+    let (old_end, new_end) = (hunk.range.old_end(), hunk.range.new_end());
+
+    let count = next_old_first.saturating_sub(old_end)
+        .min(next_new_first.saturating_sub(new_end))
+        .min(limit);
+
+    let revealed = (0..count).map(|offset| context_line(old_end + offset, new_end + offset, old, new));
+
+    hunk.lines.extend(revealed);
+    hunk.range.old_lines += count;
+    hunk.range.new_lines += count;
+}
+
+// unchanged lines are shown as they are in the new version, as git does for context lines
+fn context_line(old_lineno: u32, new_lineno: u32, old: &[SourceLine], new: &[SourceLine]) -> Change {
+    let line = new
+        .get(new_lineno as usize - 1)
+        .or_else(|| old.get(old_lineno as usize - 1));
+
+    Change {
+        text: line.map_or(String::new(), |line| line.text.clone()),
+        kind: ChangeKind::Context,
+        encoding: line.and_then(|line| line.encoding),
+        old_lineno: Some(old_lineno),
+        new_lineno: Some(new_lineno),
+    }
+}
+
+fn merge_touching(hunks: Vec<Hunk>) -> Vec<Hunk> {
+    let mut merged: Vec<Hunk> = Vec::new();
+
+    for hunk in hunks {
+        match merged.last_mut() {
+            Some(prev) if prev.range.old_end() == hunk.range.old_first && prev.range.new_end() == hunk.range.new_first => {
+                prev.range.old_lines += hunk.range.old_lines;
+                prev.range.new_lines += hunk.range.new_lines;
+                prev.lines.extend(hunk.lines);
+            },
+            _ => merged.push(hunk),
+        }
+    }
+
+    merged
+}
+
+// reads a `@@ -a,b +c,d @@ section` header as produced by libgit2
+fn parse_header(header: &str) -> Option<(HunkRange, String)> {
+    let (ranges, section) = header.strip_prefix("@@ -")?.split_once(" @@")?;
+    let (old, new) = ranges.split_once(" +")?;
+
+    let (old_first, old_lines) = parse_range(old)?;
+    let (new_first, new_lines) = parse_range(new)?;
+
+    Some((HunkRange { old_first, old_lines, new_first, new_lines }, section.to_string()))
+}
+
+fn parse_range(range: &str) -> Option<(u32, u32)> {
+    let (start, lines) = match range.split_once(',') {
+        Some((start, lines)) => (start.parse().ok()?, lines.parse().ok()?),
+        None => (range.parse().ok()?, 1),
+    };
+
+    // an empty range starts at the line before where it would be
+    Some((if lines == 0 { start + 1 } else { start }, lines))
+}
+
+fn format_range(first: u32, lines: u32) -> String {
+    match lines {
+        0 => format!("{},0", first - 1),
+        1 => first.to_string(),
+        _ => format!("{first},{lines}"),
+    }
+}
+
+impl HunkRange {
+    // the first line after the hunk on each side
+    fn old_end(&self) -> u32 {
+        self.old_first + self.old_lines
+    }
+
+    fn new_end(&self) -> u32 {
+        self.new_first + self.new_lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(texts: &[String]) -> Vec<SourceLine> {
+        texts.iter().map(|text| SourceLine { text: format!("{text}\n"), encoding: None }).collect()
+    }
+
+    fn numbered(count: u32) -> Vec<String> {
+        (1..=count).map(|n| format!("l{n}")).collect()
+    }
+
+    fn header(text: &str) -> Change {
+        Change { text: text.to_string(), kind: ChangeKind::HunkHeader, encoding: None, old_lineno: None, new_lineno: None }
+    }
+
+    fn deletion(old_lineno: u32, text: &str) -> Change {
+        Change { text: format!("{text}\n"), kind: ChangeKind::Deletion, encoding: None, old_lineno: Some(old_lineno), new_lineno: None }
+    }
+
+    fn insertion(new_lineno: u32, text: &str) -> Change {
+        Change { text: format!("{text}\n"), kind: ChangeKind::Insertion, encoding: None, old_lineno: None, new_lineno: Some(new_lineno) }
+    }
+
+    fn headers(changes: &[Change]) -> Vec<&str> {
+        changes
+            .iter()
+            .filter(|change| matches!(change.kind, ChangeKind::HunkHeader))
+            .map(|change| change.text.as_str())
+            .collect()
+    }
+
+    fn context_linenos(changes: &[Change]) -> Vec<(u32, u32)> {
+        changes
+            .iter()
+            .filter(|change| matches!(change.kind, ChangeKind::Context))
+            .map(|change| (change.old_lineno.unwrap(), change.new_lineno.unwrap()))
+            .collect()
+    }
+
+    // a 30 line file with line 15 replaced, diffed without any context
+    fn replaced_line() -> (Vec<Change>, Vec<SourceLine>, Vec<SourceLine>) {
+        let old = numbered(30);
+        let mut new = old.clone();
+        new[14] = String::from("x15");
+
+        let changes = vec![header("@@ -15 +15 @@"), deletion(15, "l15"), insertion(15, "x15")];
+
+        (changes, source(&old), source(&new))
+    }
+
+    #[test]
+    fn parses_and_formats_ranges() {
+        assert_eq!(parse_range("3"), Some((3, 1)));
+        assert_eq!(parse_range("3,4"), Some((3, 4)));
+        // an empty range names the line before it
+        assert_eq!(parse_range("4,0"), Some((5, 0)));
+        assert_eq!(parse_range("0,0"), Some((1, 0)));
+
+        assert_eq!(format_range(3, 1), "3");
+        assert_eq!(format_range(3, 4), "3,4");
+        assert_eq!(format_range(5, 0), "4,0");
+        assert_eq!(format_range(1, 0), "0,0");
+    }
+
+    #[test]
+    fn keeps_the_section_of_a_header() {
+        let (range, section) = parse_header("@@ -1,2 +1,3 @@ fn main() {").unwrap();
+
+        assert_eq!((range.old_first, range.old_lines, range.new_first, range.new_lines), (1, 2, 1, 3));
+        assert_eq!(section, " fn main() {");
+        assert!(parse_header("not a header").is_none());
+    }
+
+    #[test]
+    fn expands_above() {
+        let (mut changes, old, new) = replaced_line();
+
+        expand(&mut changes, 1, Expansion::Above, &old, &new);
+
+        assert_eq!(headers(&changes), ["@@ -5,11 +5,11 @@"]);
+        assert_eq!(context_linenos(&changes), (5..15).map(|n| (n, n)).collect::<Vec<_>>());
+        assert_eq!(changes[1].text, "l5\n");
+    }
+
+    #[test]
+    fn expands_below() {
+        let (mut changes, old, new) = replaced_line();
+
+        expand(&mut changes, 1, Expansion::Below, &old, &new);
+
+        assert_eq!(headers(&changes), ["@@ -15,11 +15,11 @@"]);
+        assert_eq!(context_linenos(&changes), (16..26).map(|n| (n, n)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn expands_to_the_whole_file() {
+        let (mut changes, old, new) = replaced_line();
+
+        expand(&mut changes, 1, Expansion::Full, &old, &new);
+
+        assert_eq!(headers(&changes), ["@@ -1,30 +1,30 @@"]);
+        assert_eq!(changes.len(), 1 + 29 + 2);
+    }
+
+    #[test]
+    fn stops_at_the_ends_of_the_file() {
+        let old = numbered(5);
+        let mut new = old.clone();
+        new[1] = String::from("x2");
+
+        let mut changes = vec![header("@@ -2 +2 @@"), deletion(2, "l2"), insertion(2, "x2")];
+
+        expand(&mut changes, 0, Expansion::Above, &source(&old), &source(&new));
+        expand(&mut changes, 0, Expansion::Below, &source(&old), &source(&new));
+
+        assert_eq!(headers(&changes), ["@@ -1,5 +1,5 @@"]);
+        assert_eq!(context_linenos(&changes), [(1, 1), (3, 3), (4, 4), (5, 5)]);
+    }
+
+    #[test]
+    fn expands_a_pure_insertion() {
+        // `n` inserted after line 4 of 10
+        let old = numbered(10);
+        let mut new = old.clone();
+        new.insert(4, String::from("n"));
+
+        let (old, new) = (source(&old), source(&new));
+        let mut changes = vec![header("@@ -4,0 +5 @@"), insertion(5, "n")];
+
+        expand(&mut changes, 1, Expansion::Above, &old, &new);
+
+        assert_eq!(headers(&changes), ["@@ -1,4 +1,5 @@"]);
+        assert_eq!(context_linenos(&changes), [(1, 1), (2, 2), (3, 3), (4, 4)]);
+
+        expand(&mut changes, 1, Expansion::Below, &old, &new);
+
+        assert_eq!(headers(&changes), ["@@ -1,10 +1,11 @@"]);
+        assert_eq!(changes.last().map(|change| (change.old_lineno, change.new_lineno)), Some((Some(10), Some(11))));
+    }
+
+    #[test]
+    fn expands_a_pure_deletion() {
+        // line 5 of 10 deleted
+        let old = numbered(10);
+        let mut new = old.clone();
+        new.remove(4);
+
+        let (old, new) = (source(&old), source(&new));
+        let mut changes = vec![header("@@ -5 +4,0 @@"), deletion(5, "l5")];
+
+        expand(&mut changes, 1, Expansion::Below, &old, &new);
+
+        assert_eq!(headers(&changes), ["@@ -5,6 +5,5 @@"]);
+        assert_eq!(context_linenos(&changes), [(6, 5), (7, 6), (8, 7), (9, 8), (10, 9)]);
+
+        expand(&mut changes, 1, Expansion::Above, &old, &new);
+
+        assert_eq!(headers(&changes), ["@@ -1,10 +1,9 @@"]);
+    }
+
+    #[test]
+    fn merges_hunks_that_touch() {
+        // lines 10 and 22 of 30 replaced
+        let old = numbered(30);
+        let mut new = old.clone();
+        new[9] = String::from("x10");
+        new[21] = String::from("x22");
+
+        let (old, new) = (source(&old), source(&new));
+
+        let mut changes = vec![
+            header("@@ -10 +10 @@"),
+            deletion(10, "l10"),
+            insertion(10, "x10"),
+            header("@@ -22 +22 @@"),
+            deletion(22, "l22"),
+            insertion(22, "x22"),
+        ];
+
+        // reveals 11 to 20, leaving line 21 between the hunks
+        expand(&mut changes, 1, Expansion::Below, &old, &new);
+        assert_eq!(headers(&changes), ["@@ -10,11 +10,11 @@", "@@ -22 +22 @@"]);
+
+        // only line 21 is left to reveal, after which the hunks touch
+        expand(&mut changes, 1, Expansion::Below, &old, &new);
+        assert_eq!(headers(&changes), ["@@ -10,13 +10,13 @@"]);
+
+        let kinds: Vec<ChangeKind> = changes.iter().map(|change| change.kind).collect();
+        assert_eq!(kinds.iter().filter(|kind| matches!(kind, ChangeKind::Deletion)).count(), 2);
+        assert_eq!(changes.iter().find(|change| change.new_lineno == Some(21)).map(|change| change.text.as_str()), Some("l21\n"));
+    }
+
+    #[test]
+    fn leaves_unreadable_headers_alone() {
+        let (_, old, new) = replaced_line();
+        let mut changes = vec![header("@@ garbled @@"), deletion(15, "l15")];
+
+        expand(&mut changes, 1, Expansion::Full, &old, &new);
+
+        assert_eq!(headers(&changes), ["@@ garbled @@"]);
+        assert_eq!(changes.len(), 2);
+    }
+}
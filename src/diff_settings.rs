@@ -0,0 +1,134 @@
+use clap::ValueEnum;
+use git2::{Config, DiffOptions};
+
+/*
+ * Options controlling how diffs are computed. These are read from git config, where
+ * `branch-diff.*` keys take precedence over git's own `diff.*` keys, and can then be
+ * overridden by command line flags or toggled at runtime.
+ * */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DiffSettings {
+    pub context_lines: u32,
+    pub ignore_whitespace: bool,
+    pub ignore_whitespace_change: bool,
+    pub ignore_blank_lines: bool,
+    pub algorithm: DiffAlgorithm,
+    pub indent_heuristic: bool,
+    pub rename_threshold: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum)]
+pub enum DiffAlgorithm {
+    Myers,
+    Minimal,
+    Patience,
+}
+
+impl Default for DiffSettings {
+    fn default() -> Self {
+        DiffSettings {
+            context_lines: 3,
+            ignore_whitespace: false,
+            ignore_whitespace_change: false,
+            ignore_blank_lines: false,
+            algorithm: DiffAlgorithm::Myers,
+            indent_heuristic: true,
+            rename_threshold: 50,
+        }
+    }
+}
+
+impl DiffSettings {
+    pub fn from_config(config: &Config) -> Self {
+        let mut settings = DiffSettings::default();
+
+        let get_i32 = |keys: &[&str]| keys.iter().find_map(|key| config.get_i32(key).ok());
+        let get_bool = |keys: &[&str]| keys.iter().find_map(|key| config.get_bool(key).ok());
+        let get_string = |keys: &[&str]| keys.iter().find_map(|key| config.get_string(key).ok());
+
+        if let Some(lines) = get_i32(&["branch-diff.context", "diff.context"]) {
+            settings.context_lines = lines.max(0) as u32;
+        }
+
+        if let Some(ignore) = get_bool(&["branch-diff.ignoreWhitespace"]) {
+            settings.ignore_whitespace = ignore;
+        }
+
+        if let Some(ignore) = get_bool(&["branch-diff.ignoreWhitespaceChange"]) {
+            settings.ignore_whitespace_change = ignore;
+        }
+
+        if let Some(ignore) = get_bool(&["branch-diff.ignoreBlankLines"]) {
+            settings.ignore_blank_lines = ignore;
+        }
+
+        if let Some(name) = get_string(&["branch-diff.algorithm", "diff.algorithm"]) {
+            match DiffAlgorithm::from_str(name.as_str(), true) {
+                Ok(algorithm) => settings.algorithm = algorithm,
+                // git accepts `default` as an alias for myers
+                Err(_) if name == "default" => settings.algorithm = DiffAlgorithm::Myers,
+                // libgit2 has no histogram implementation, so `histogram` ends up here too
+                Err(_) => tracing::error!("unknown diff algorithm in config: {name}"),
+            }
+        }
+
+        if let Some(heuristic) = get_bool(&["branch-diff.indentHeuristic", "diff.indentHeuristic"]) {
+            settings.indent_heuristic = heuristic;
+        }
+
+        if let Some(threshold) = get_i32(&["branch-diff.renameThreshold"]) {
+            settings.rename_threshold = threshold.clamp(0, 100) as u16;
+        }
+
+        settings
+    }
+
+    pub fn diff_options(&self) -> DiffOptions {
+        let mut opts = DiffOptions::new();
+
+        opts.context_lines(self.context_lines)
+            .ignore_whitespace(self.ignore_whitespace)
+            .ignore_whitespace_change(self.ignore_whitespace_change)
+            .ignore_blank_lines(self.ignore_blank_lines)
+            .minimal(self.algorithm == DiffAlgorithm::Minimal)
+            .patience(self.algorithm == DiffAlgorithm::Patience)
+            .indent_heuristic(self.indent_heuristic);
+
+        opts
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = vec![
+            format!("-U{}", self.context_lines),
+            format!("{:?}", self.algorithm).to_lowercase(),
+        ];
+
+        if self.ignore_whitespace {
+            parts.push(String::from("-w"));
+        }
+
+        if self.ignore_whitespace_change {
+            parts.push(String::from("-b"));
+        }
+
+        if self.ignore_blank_lines {
+            parts.push(String::from("--ignore-blank-lines"));
+        }
+
+        if !self.indent_heuristic {
+            parts.push(String::from("--no-indent-heuristic"));
+        }
+
+        parts.join(" ")
+    }
+}
+
+impl DiffAlgorithm {
+    pub fn next(self) -> Self {
+        match self {
+            DiffAlgorithm::Myers => DiffAlgorithm::Minimal,
+            DiffAlgorithm::Minimal => DiffAlgorithm::Patience,
+            DiffAlgorithm::Patience => DiffAlgorithm::Myers,
+        }
+    }
+}
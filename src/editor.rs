@@ -0,0 +1,79 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::Builder;
+
+/*
+ * Runs the user's editor on a file at the given line, waiting for it to exit. The editor
+ * comes from `$VISUAL` or `$EDITOR`, and can include its own arguments, e.g. `code --wait`.
+ * */
+pub fn open(path: &Path, line: u32) -> io::Result<()> {
+    let editor = env::var("VISUAL")
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .or_else(|| env::var("EDITOR").ok().filter(|editor| !editor.trim().is_empty()))
+        .unwrap_or_else(|| String::from("vi"));
+
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+
+    let status = Command::new(program)
+        .args(words)
+        .args(line_args(program, path, line))
+        .status()?;
+
+    if !status.success() {
+        return Err(io::Error::other(format!("{program} exited with {status}")));
+    }
+
+    Ok(())
+}
+
+/*
+ * Writes a version of a file that isn't in the working tree to a read-only temporary file,
+ * named after the original so the editor can tell its language. The file is left behind
+ * rather than removed once the editor exits, since graphical editors may return before
+ * they've opened it.
+ * */
+pub fn read_only_copy(path: &str, content: &[u8]) -> io::Result<PathBuf> {
+    let name = Path::new(path).file_name().map_or(OsString::from("file"), OsString::from);
+
+    let mut suffix = OsString::from("-");
+    suffix.push(name);
+
+    let copy = Builder::new()
+        .prefix("branch-diff-")
+        .suffix(&suffix)
+        .tempfile()?;
+
+    fs::write(copy.path(), content)?;
+
+    let (_, copy_path) = copy.keep().map_err(|e| e.error)?;
+
+    let mut permissions = fs::metadata(&copy_path)?.permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&copy_path, permissions)?;
+
+    Ok(copy_path)
+}
+
+// editors that don't take `+N` before the file want the line after it, as `file:N`
+fn line_args(program: &str, path: &Path, line: u32) -> Vec<OsString> {
+    let name = Path::new(program)
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or(program);
+
+    let mut file_and_line = path.as_os_str().to_os_string();
+    file_and_line.push(format!(":{line}"));
+
+    match name {
+        "code" | "code-insiders" | "codium" | "cursor" => vec![OsString::from("--goto"), file_and_line],
+        "hx" | "helix" | "subl" | "zed" | "micro" => vec![file_and_line],
+        // vi, vim, nvim, emacs, emacsclient, nano, kak and most others
+        _ => vec![OsString::from(format!("+{line}")), path.as_os_str().to_os_string()],
+    }
+}
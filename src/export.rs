@@ -0,0 +1,366 @@
+use clap::ValueEnum;
+use std::path::Path;
+
+use crate::file_tree::FileTree;
+use crate::repo::{Repo, RepoError};
+use crate::review::{Comment, LineAnchor, Review, Side};
+use crate::state::{Change, ChangeKind, Commit, CommitKind};
+
+// lines shown above a single line comment, as GitHub does when it quotes one
+const EXCERPT_CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+}
+
+// a commit's part of the review, with its comments grouped by file in the order of its diff
+struct CommitReview<'a> {
+    commit: &'a Commit,
+    note: Option<&'a str>,
+    files: Vec<FileReview<'a>>,
+}
+
+struct FileReview<'a> {
+    path: &'a str,
+    comments: Vec<(&'a Comment, Vec<String>)>,
+}
+
+impl ExportFormat {
+    // JSON for a `.json` file, and Markdown otherwise
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => ExportFormat::Json,
+            _ => ExportFormat::Markdown,
+        }
+    }
+}
+
+// what the review is of, as the bottom bar puts it
+pub fn review_title(from: Option<&str>, into: Option<&str>) -> String {
+    match (from, into) {
+        (Some(from), Some(into)) => format!("{from} into {into}"),
+        _ => String::from("the working tree"),
+    }
+}
+
+/*
+ * Writes out the review of the given commits, in the order they're listed. Diffs are
+ * computed again with the repository's current settings to quote the lines each comment
+ * is on, and comments on lines outside of them are written without a quote.
+ * */
+pub fn export(repository: &Repo, title: &str, commits: &[&Commit], review: &Review, format: ExportFormat) -> Result<String, RepoError> {
+    let reviews = commits
+        .iter()
+        .map(|commit| commit_review(repository, commit, review))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(match format {
+        ExportFormat::Markdown => markdown(title, &reviews),
+        ExportFormat::Json => json(title, &reviews),
+    })
+}
+
+fn commit_review<'a>(repository: &Repo, commit: &'a Commit, review: &'a Review) -> Result<CommitReview<'a>, RepoError> {
+    let mut comments: Vec<&Comment> = review.comments
+        .iter()
+        .filter(|comment| comment.commit == commit.hash)
+        .collect();
+
+    let file_tree = match comments.is_empty() {
+        true => None,
+        false => Some(repository.commit_diff(commit.hash.as_str(), commit.kind, commit.merge_diff)?),
+    };
+
+    let diff_file = |path: &str| file_tree
+        .iter()
+        .flat_map(FileTree::iter_files)
+        .find(|file| file.path == path);
+
+    // comments hidden in the diff pane because their file has changed since are left out too
+    comments.retain(|comment| match diff_file(comment.path.as_str()) {
+        Some(file) => comment.is_on_version(file.blob_ids),
+        None => commit.kind == CommitKind::Commit,
+    });
+
+    let mut paths: Vec<&str> = Vec::new();
+
+    for comment in &comments {
+        if !paths.contains(&comment.path.as_str()) {
+            paths.push(comment.path.as_str());
+        }
+    }
+
+    let diff_position = |path: &str| file_tree
+        .iter()
+        .flat_map(FileTree::iter_files)
+        .position(|file| file.path == path);
+
+    // files that aren't in the diff, e.g. because of the paths given, go last
+    paths.sort_by_key(|path| diff_position(path).unwrap_or(usize::MAX));
+
+    let files = paths
+        .into_iter()
+        .map(|path| {
+            let changes = diff_file(path).map_or(&[][..], |file| file.changes);
+
+            FileReview {
+                path,
+                comments: comments
+                    .iter()
+                    .filter(|comment| comment.path == path)
+                    .map(|comment| (*comment, excerpt(changes, comment)))
+                    .collect(),
+            }
+        })
+        .collect();
+
+    Ok(CommitReview { commit, note: review.note(commit.hash.as_str()), files })
+}
+
+// the diff lines a comment covers, as they'd appear in a patch
+fn excerpt(changes: &[Change], comment: &Comment) -> Vec<String> {
+    let position = |anchor: &LineAnchor| changes.iter().position(|change| anchor.is_on(change));
+
+    let Some(end) = position(&comment.end) else {
+        return Vec::new();
+    };
+
+    let start = match comment.start.as_ref().and_then(position) {
+        Some(start) if start <= end => start,
+        _ => {
+            let hunk_start = changes[..end]
+                .iter()
+                .rposition(|change| matches!(change.kind, ChangeKind::HunkHeader))
+                .map_or(0, |idx| idx + 1);
+
+            end.saturating_sub(EXCERPT_CONTEXT).max(hunk_start)
+        },
+    };
+
+    changes[start..=end]
+        .iter()
+        .filter_map(|change| {
+            let prefix = match change.kind {
+                ChangeKind::Insertion => '+',
+                ChangeKind::Deletion => '-',
+                ChangeKind::Context => ' ',
+                ChangeKind::HunkHeader | ChangeKind::Comment => return None,
+            };
+
+            Some(format!("{prefix}{}", change.text.trim_end_matches('\n')))
+        })
+        .collect()
+}
+
+fn subject(commit: &Commit) -> &str {
+    commit.message.as_deref().and_then(|message| message.lines().next()).unwrap_or_default()
+}
+
+fn markdown(title: &str, reviews: &[CommitReview]) -> String {
+    let mut document = format!("# Review of {title}\n");
+
+    for review in reviews {
+        document.push_str(&format!("\n## {} {}\n", review.commit.short_hash, subject(review.commit)));
+
+        if let Some(note) = review.note {
+            document.push_str(&format!("\n{note}\n"));
+        }
+
+        if review.note.is_none() && review.files.is_empty() {
+            document.push_str("\n_No comments._\n");
+        }
+
+        for file in &review.files {
+            document.push_str(&format!("\n### `{}`\n", file.path));
+
+            for (comment, excerpt) in &file.comments {
+                if !excerpt.is_empty() {
+                    document.push_str(&format!("\n```diff\n{}\n```\n", excerpt.join("\n")));
+                }
+
+                document.push_str(&format!("\n> **{}:** {}\n", comment.lines(), comment.body.replace('\n', "\n> ")));
+            }
+        }
+    }
+
+    document
+}
+
+/*
+ * Each comment is shaped like the body of GitHub's request to create a pull request review
+ * comment, so it can be posted as it is. The staged, unstaged and whole branch entries have
+ * no commit to be posted against, so they're left out along with their comments.
+ * */
+fn json(title: &str, reviews: &[CommitReview]) -> String {
+    let commits: Vec<String> = reviews
+        .iter()
+        .filter(|review| review.commit.kind == CommitKind::Commit)
+        .map(|review| format!(
+            "    {{\"commit_id\": {}, \"subject\": {}, \"note\": {}}}",
+            json_string(review.commit.hash.as_str()),
+            json_string(subject(review.commit)),
+            review.note.map_or(String::from("null"), json_string),
+        ))
+        .collect();
+
+    let comments: Vec<String> = reviews
+        .iter()
+        .filter(|review| review.commit.kind == CommitKind::Commit)
+        .flat_map(|review| review.files.iter().flat_map(|file| &file.comments))
+        .map(|(comment, _)| {
+            let mut fields = vec![
+                format!("\"commit_id\": {}", json_string(comment.commit.as_str())),
+                format!("\"path\": {}", json_string(comment.path.as_str())),
+                format!("\"side\": \"{}\"", github_side(comment.end.side)),
+                format!("\"line\": {}", comment.end.line),
+            ];
+
+            if let Some(start) = comment.start {
+                fields.push(format!("\"start_side\": \"{}\"", github_side(start.side)));
+                fields.push(format!("\"start_line\": {}", start.line));
+            }
+
+            fields.push(format!("\"body\": {}", json_string(comment.body.as_str())));
+
+            format!("    {{{}}}", fields.join(", "))
+        })
+        .collect();
+
+    format!(
+        "{{\n  \"title\": {},\n  \"commits\": {},\n  \"comments\": {}\n}}\n",
+        json_string(title),
+        json_array(&commits),
+        json_array(&comments),
+    )
+}
+
+// one item per line, already indented
+fn json_array(items: &[String]) -> String {
+    match items.is_empty() {
+        true => String::from("[]"),
+        false => format!("[\n{}\n  ]", items.join(",\n")),
+    }
+}
+
+// GitHub calls the old side of a diff the left and the new side the right
+fn github_side(side: Side) -> &'static str {
+    match side {
+        Side::Old => "LEFT",
+        Side::New => "RIGHT",
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut escaped = String::from("\"");
+
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff_settings::DiffSettings;
+    use crate::state::MergeDiff;
+    use serde_json::{Value, json};
+
+    fn commit(hash: &str, message: &str, kind: CommitKind) -> Commit {
+        Commit {
+            hash: hash.to_string(),
+            short_hash: hash[..7].to_string(),
+            message: Some(message.to_string()),
+            author: None,
+            committer: None,
+            parents: Vec::new(),
+            trailers: Vec::new(),
+            kind,
+            merge_diff: MergeDiff::FirstParent,
+            signature: None,
+            verdicts: Vec::new(),
+            diff_settings: DiffSettings::default(),
+            file_tree: None,
+            diff_len: 0,
+            outside_paths: false,
+        }
+    }
+
+    fn comment(commit: &str, path: &str, body: &str, start: Option<LineAnchor>) -> Comment {
+        Comment {
+            commit: commit.to_string(),
+            path: path.to_string(),
+            end: LineAnchor { side: Side::New, line: 12 },
+            start,
+            body: body.to_string(),
+            blob_ids: None,
+        }
+    }
+
+    #[test]
+    fn json_escapes_paths_and_bodies() {
+        let hash = "0123456789abcdef0123456789abcdef01234567";
+        let path = "docs/\"quoted\" \\ naïve\tname.md";
+        let body = "line one\nline\ttwo with a \u{1b}[1m bell\u{7} and 日本語 \"quotes\" \\ backslash\r";
+
+        let reviewed = commit(hash, "Fix \"the\" thing\n\nbody", CommitKind::Commit);
+        let staged = commit("index..HEAD", "Staged changes", CommitKind::Staged);
+
+        let single = comment(hash, path, body, None);
+        let range = comment(hash, path, "range", Some(LineAnchor { side: Side::Old, line: 10 }));
+        let on_staged = comment("index..HEAD", path, "left out", None);
+
+        let reviews = [
+            CommitReview {
+                commit: &staged,
+                note: Some("left out"),
+                files: vec![FileReview { path, comments: vec![(&on_staged, Vec::new())] }],
+            },
+            CommitReview {
+                commit: &reviewed,
+                note: Some("note\twith \"escapes\"\n"),
+                files: vec![FileReview { path, comments: vec![(&single, Vec::new()), (&range, Vec::new())] }],
+            },
+        ];
+
+        let exported: Value = serde_json::from_str(&json("feat/ü into main", &reviews)).unwrap();
+
+        assert_eq!(exported, json!({
+            "title": "feat/ü into main",
+            "commits": [
+                {"commit_id": hash, "subject": "Fix \"the\" thing", "note": "note\twith \"escapes\"\n"},
+            ],
+            "comments": [
+                {"commit_id": hash, "path": path, "side": "RIGHT", "line": 12, "body": body},
+                {
+                    "commit_id": hash,
+                    "path": path,
+                    "side": "RIGHT",
+                    "line": 12,
+                    "start_side": "LEFT",
+                    "start_line": 10,
+                    "body": "range",
+                },
+            ],
+        }));
+    }
+
+    #[test]
+    fn json_without_comments_is_still_valid() {
+        let exported: Value = serde_json::from_str(&json("the working tree", &[])).unwrap();
+
+        assert_eq!(exported, json!({"title": "the working tree", "commits": [], "comments": []}));
+    }
+}
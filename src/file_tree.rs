@@ -1,5 +1,12 @@
-use crate::state::Change;
+use std::cell::OnceCell;
 
+use crate::context::{self, Expansion, SourceLine};
+use crate::highlight::{self, HighlightedLine};
+use crate::state::{Change, ChangeKind};
+use crate::word_diff::{self, ChangedRanges};
+
+// most nodes are files, so boxing them would only add an allocation per file
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum FileTree {
     Directory {
@@ -8,18 +15,112 @@ pub enum FileTree {
     },
     File {
         name: String,
+        path: String,
+        old_path: Option<String>,
+        blob_ids: BlobIds,
         changes: Vec<Change>,
         change_kind: FileChangeKind,
+        content_kind: FileContentKind,
+        // the old and new modes, when the file's mode changed alongside anything else
+        mode_change: Option<(u32, u32)>,
         scroll_start: usize,
         hunks: Vec<usize>,
+        // how `changes` pair up into rows when shown side by side
+        split_rows: Vec<SplitRow>,
+        view: DiffView,
+        render_cache: Box<RenderCache>,
     },
 }
 
+// styling worked out the first time a file is shown, since it's only needed for rendering
+#[derive(Debug, Default)]
+pub struct RenderCache {
+    // syntax highlighting for each change
+    highlights: OnceCell<Option<Vec<HighlightedLine>>>,
+    // the parts of each change that differ from the line it's paired with
+    emphasis: OnceCell<Vec<ChangedRanges>>,
+}
+
+// the file's blob on each side of the diff, None on the side where it doesn't exist
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlobIds {
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/*
+ * How a diff is laid out. `Unified` shows one change per row, while `Split` shows the old
+ * and new sides next to each other, so a file can take a different number of rows in each.
+ * */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DiffView {
+    #[default]
+    Unified,
+    Split,
+}
+
+// indices into a file's changes shown on the left (old) and right (new) of a split row
+#[derive(Debug, Clone, Copy)]
+pub struct SplitRow {
+    pub old: Option<usize>,
+    pub new: Option<usize>,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum FileChangeKind {
     Creation = 0,
     Deletion = 1,
     Change = 2,
+    Rename = 3,
+    Copy = 4,
+}
+
+/*
+ * Describes what kind of content a file entry holds. Anything other than `Text` has no
+ * lines to show and is rendered as a single placeholder line in the diff pane.
+ * */
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileContentKind {
+    Text,
+    Binary { old_size: u64, new_size: u64 },
+    ModeChange { old_mode: u32, new_mode: u32 },
+    Symlink { old_target: Option<String>, new_target: Option<String> },
+    Empty,
+    Unchanged,
+}
+
+impl FileContentKind {
+    pub fn placeholder(&self) -> Option<String> {
+        match self {
+            Self::Text => None,
+            Self::Binary { old_size, new_size } => Some(format!("Binary file: {old_size} bytes → {new_size} bytes")),
+            Self::ModeChange { old_mode, new_mode } => Some(format!("Mode changed: {old_mode:o} → {new_mode:o}")),
+            Self::Symlink { old_target, new_target } => Some(format!(
+                "Symlink: {} → {}",
+                old_target.as_deref().unwrap_or("(none)"),
+                new_target.as_deref().unwrap_or("(none)"),
+            )),
+            Self::Empty => Some(String::from("Empty file")),
+            Self::Unchanged => Some(String::from("Content unchanged")),
+        }
+    }
+
+    pub fn row_count(&self, changes: &[Change], split_rows: &[SplitRow], view: DiffView) -> usize {
+        match (self, view) {
+            (Self::Text, DiffView::Unified) => changes.len(),
+            (Self::Text, DiffView::Split) => split_rows.len(),
+            _ => 1,
+        }
+    }
+}
+
+impl DiffView {
+    pub fn toggle(self) -> Self {
+        match self {
+            DiffView::Unified => DiffView::Split,
+            DiffView::Split => DiffView::Unified,
+        }
+    }
 }
 
 impl FileTree {
@@ -45,13 +146,16 @@ impl FileTree {
         FileTreeFilesIter::new(self)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn insert_file(
         &mut self,
         path: &str,
+        old_path: Option<String>,
+        blob_ids: BlobIds,
         changes: Vec<Change>,
         change_kind: FileChangeKind,
-        hunks: Vec<usize>,
-        scroll_start: usize,
+        content_kind: FileContentKind,
+        mode_change: Option<(u32, u32)>,
     ) {
         let mut segments = path.split('/').peekable();
         let mut current_tree = self;
@@ -60,12 +164,23 @@ impl FileTree {
             match current_tree {
                 Self::Directory { children, .. } => {
                     if segments.peek().is_none() {
+                        let hunks = hunk_offsets(&changes);
+                        let split_rows = split_rows(&changes);
+
                         children.push(Self::File {
                             name: seg.to_string(),
+                            path: path.to_string(),
+                            old_path,
+                            blob_ids,
                             changes,
                             change_kind,
-                            scroll_start,
+                            content_kind,
+                            mode_change,
+                            scroll_start: 0,
                             hunks,
+                            split_rows,
+                            view: DiffView::Unified,
+                            render_cache: Box::default(),
                         });
                         return;
                     }
@@ -94,7 +209,7 @@ impl FileTree {
 
     pub fn sort(&mut self) {
         if let Self::Directory { children, .. } = self {
-            children.sort_by(|a,b| a.name().cmp(&b.name()));
+            children.sort_by(|a,b| a.name().cmp(b.name()));
             children.reverse();
 
             for child in children {
@@ -103,21 +218,213 @@ impl FileTree {
         }
     }
 
-    pub fn get_next_hunk(&self, start_at: usize) -> Option<usize> {
-        let mut prev_hunk_idx = 0;
+    /*
+     * Assigns each file's `scroll_start` so that files follow each other in the same order
+     * as `iter_files` yields them, taking as many rows as they need in the given view. Must
+     * be called again whenever the tree is re-sorted or the view changes.
+     * */
+    pub fn layout(&mut self, view: DiffView) {
+        self.layout_from(0, view);
+    }
+
+    fn layout_from(&mut self, start: usize, new_view: DiffView) -> usize {
+        match self {
+            Self::Directory { children, .. } => {
+                // iter_files pops children off a stack, so they are visited last to first
+                children
+                    .iter_mut()
+                    .rev()
+                    .fold(start, |next_start, child| child.layout_from(next_start, new_view))
+            },
+            Self::File { changes, content_kind, scroll_start, split_rows, view, .. } => {
+                *scroll_start = start;
+                *view = new_view;
+                start + content_kind.row_count(changes, split_rows, new_view)
+            },
+        }
+    }
+
+    /*
+     * Reveals more unchanged lines around the hunk holding the given change of the
+     * `file_idx`th file, from the old and new versions of the file. The tree must be laid
+     * out again afterwards, since the file takes up more rows.
+     * */
+    pub fn expand_context(
+        &mut self,
+        file_idx: usize,
+        change_idx: usize,
+        expansion: Expansion,
+        old_lines: &[SourceLine],
+        new_lines: &[SourceLine],
+    ) {
+        let Some(Self::File { changes, hunks, split_rows: rows, render_cache, .. }) = self.files_mut().into_iter().nth(file_idx) else {
+            return;
+        };
+
+        context::expand(changes, change_idx, expansion, old_lines, new_lines);
+
+        *hunks = hunk_offsets(changes);
+        *rows = split_rows(changes);
+        // the cached styling is indexed by change, which no longer line up
+        **render_cache = RenderCache::default();
+    }
 
-        for node in self.iter_files() {
-            for hunk_idx in node.hunks {
-                if prev_hunk_idx <= start_at && *hunk_idx > start_at {
-                    return Some(*hunk_idx);
+    /*
+     * Puts a comment row under each line with comments attached, replacing the comment rows
+     * already there. `comments` gives the text of each comment on a line of the file with the
+     * given path and blobs. The tree must be laid out again afterwards, since files may take
+     * up a different number of rows.
+     * */
+    pub fn set_comments(&mut self, comments: impl Fn(&str, &BlobIds, &Change) -> Vec<String>) {
+        for file in self.files_mut() {
+            let Self::File { path, blob_ids, changes, hunks, split_rows: rows, render_cache, .. } = file else {
+                continue;
+            };
+
+            let comment_rows = |changes: &[Change]| -> Vec<(usize, String)> {
+                changes
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, change)| matches!(change.kind, ChangeKind::Comment))
+                    .map(|(idx, change)| (idx, change.text.clone()))
+                    .collect()
+            };
+
+            let before = comment_rows(changes);
+            let mut annotated = Vec::new();
+
+            for change in std::mem::take(changes) {
+                if matches!(change.kind, ChangeKind::Comment) {
+                    continue;
                 }
 
-                prev_hunk_idx = *hunk_idx;
+                let texts = comments(path, blob_ids, &change);
+                annotated.push(change);
+
+                annotated.extend(texts.into_iter().map(|text| Change {
+                    text,
+                    kind: ChangeKind::Comment,
+                    encoding: None,
+                    old_lineno: None,
+                    new_lineno: None,
+                }));
+            }
+
+            *changes = annotated;
+
+            // spares the cached styling when nothing moved
+            if comment_rows(changes) != before {
+                *hunks = hunk_offsets(changes);
+                *rows = split_rows(changes);
+                **render_cache = RenderCache::default();
             }
         }
+    }
 
-        None
+    // the file nodes in the same order as `iter_files` yields them
+    fn files_mut(&mut self) -> Vec<&mut FileTree> {
+        let mut files = Vec::new();
+        let mut stack = vec![self];
+
+        while let Some(node) = stack.pop() {
+            match node {
+                Self::Directory { children, .. } => stack.extend(children.iter_mut()),
+                file => files.push(file),
+            }
+        }
+
+        files
+    }
+
+    // returns the index and contents of the file whose diff contains the given line
+    pub fn file_at(&self, line: usize) -> Option<(usize, FileTreeFilesItem<'_>)> {
+        self.iter_files()
+            .enumerate()
+            .find(|(_, file)| line < file.scroll_start + file.line_count())
+    }
+
+    pub fn diff_len(&self) -> usize {
+        self.iter_files()
+            .map(|file| file.line_count())
+            .sum()
+    }
+
+    pub fn get_next_hunk(&self, start_at: usize) -> Option<usize> {
+        self.hunk_starts().find(|&line| line > start_at)
+    }
+
+    pub fn get_prev_hunk(&self, start_at: usize) -> Option<usize> {
+        self.hunk_starts().filter(|&line| line < start_at).last()
+    }
+
+    pub fn get_next_file(&self, start_at: usize) -> Option<usize> {
+        self.iter_files().map(|file| file.scroll_start).find(|&line| line > start_at)
+    }
+
+    pub fn get_prev_file(&self, start_at: usize) -> Option<usize> {
+        self.iter_files().map(|file| file.scroll_start).filter(|&line| line < start_at).last()
     }
+
+    // hunk offsets are stored relative to their file, so shift them by where the file starts
+    fn hunk_starts(&self) -> impl Iterator<Item = usize> + '_ {
+        self.iter_files()
+            .flat_map(|file| file.hunks.iter().map(move |&hunk| file.scroll_start + file.row_of(hunk)))
+    }
+}
+
+// every hunk starts with its header, so the hunks are wherever there's a header
+fn hunk_offsets(changes: &[Change]) -> Vec<usize> {
+    changes
+        .iter()
+        .enumerate()
+        .filter(|(_, change)| matches!(change.kind, ChangeKind::HunkHeader))
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+/*
+ * Pairs runs of deletions with the insertions that follow them so they sit side by side,
+ * leaving the shorter side empty. Context lines appear on both sides, and since every hunk
+ * starts with a header row, runs never pair up across a hunk boundary.
+ * */
+fn split_rows(changes: &[Change]) -> Vec<SplitRow> {
+    let mut rows = Vec::new();
+    let mut deletions = Vec::new();
+    let mut insertions = Vec::new();
+
+    let flush = |rows: &mut Vec<SplitRow>, deletions: &mut Vec<usize>, insertions: &mut Vec<usize>| {
+        for idx in 0..deletions.len().max(insertions.len()) {
+            rows.push(SplitRow { old: deletions.get(idx).copied(), new: insertions.get(idx).copied() });
+        }
+
+        deletions.clear();
+        insertions.clear();
+    };
+
+    for (idx, change) in changes.iter().enumerate() {
+        match change.kind {
+            ChangeKind::Context => {
+                flush(&mut rows, &mut deletions, &mut insertions);
+                rows.push(SplitRow { old: Some(idx), new: Some(idx) });
+            },
+            ChangeKind::Deletion => {
+                if !insertions.is_empty() {
+                    flush(&mut rows, &mut deletions, &mut insertions);
+                }
+
+                deletions.push(idx);
+            },
+            ChangeKind::Insertion => insertions.push(idx),
+            // headers and comments span the whole row, so they're shown on the old side only
+            ChangeKind::HunkHeader | ChangeKind::Comment => {
+                flush(&mut rows, &mut deletions, &mut insertions);
+                rows.push(SplitRow { old: Some(idx), new: None });
+            },
+        }
+    }
+
+    flush(&mut rows, &mut deletions, &mut insertions);
+    rows
 }
 
 #[derive(Debug)]
@@ -134,10 +441,94 @@ impl<'a> FileTreeFilesIter<'a> {
 #[derive(Debug)]
 pub struct FileTreeFilesItem<'a> {
     pub name: &'a str,
+    pub path: &'a str,
+    pub old_path: Option<&'a str>,
+    pub blob_ids: &'a BlobIds,
     pub changes: &'a Vec<Change>,
     pub change_kind: &'a FileChangeKind,
+    pub content_kind: &'a FileContentKind,
+    pub mode_change: Option<(u32, u32)>,
     pub hunks: &'a Vec<usize>,
+    pub split_rows: &'a Vec<SplitRow>,
+    pub view: DiffView,
     pub scroll_start: usize,
+    render_cache: &'a RenderCache,
+}
+
+impl<'a> FileTreeFilesItem<'a> {
+    // number of rows the file takes up in the diff, not counting its borders
+    pub fn line_count(&self) -> usize {
+        self.content_kind.row_count(self.changes, self.split_rows, self.view)
+    }
+
+    // indices of the changes on the given row, a unified row only ever has the first
+    pub fn row(&self, row: usize) -> (Option<usize>, Option<usize>) {
+        match (self.content_kind, self.view) {
+            (FileContentKind::Text, DiffView::Unified) => (Some(row).filter(|&row| row < self.changes.len()), None),
+            (FileContentKind::Text, DiffView::Split) => self.split_rows
+                .get(row)
+                .map_or((None, None), |split_row| (split_row.old, split_row.new)),
+            _ => (None, None),
+        }
+    }
+
+    // None when the file isn't text or there's no grammar for its language
+    pub fn highlights(&self) -> Option<&'a [HighlightedLine]> {
+        if *self.content_kind != FileContentKind::Text {
+            return None;
+        }
+
+        self.render_cache
+            .highlights
+            .get_or_init(|| highlight::highlight_file(self.path, self.changes))
+            .as_deref()
+    }
+
+    /*
+     * Compares each deletion with the insertion it sits next to in the split view, so the
+     * words that changed between them can be picked out. Changes without a partner, or
+     * whose partner is too different, have nothing to emphasise.
+     * */
+    pub fn emphasis(&self) -> &'a [ChangedRanges] {
+        self.render_cache.emphasis.get_or_init(|| {
+            let mut emphasis = vec![Vec::new(); self.changes.len()];
+
+            for split_row in self.split_rows {
+                let (Some(old), Some(new)) = (split_row.old, split_row.new) else {
+                    continue;
+                };
+
+                let (deletion, insertion) = (&self.changes[old], &self.changes[new]);
+
+                if !matches!((deletion.kind, insertion.kind), (ChangeKind::Deletion, ChangeKind::Insertion)) {
+                    continue;
+                }
+
+                let changed = word_diff::changed_ranges(
+                    deletion.text.trim_end_matches('\n'),
+                    insertion.text.trim_end_matches('\n'),
+                );
+
+                if let Some((old_ranges, new_ranges)) = changed {
+                    emphasis[old] = old_ranges;
+                    emphasis[new] = new_ranges;
+                }
+            }
+
+            emphasis
+        })
+    }
+
+    // the row the given change is shown on
+    pub fn row_of(&self, change_idx: usize) -> usize {
+        match self.view {
+            DiffView::Unified => change_idx,
+            DiffView::Split => self.split_rows
+                .iter()
+                .position(|split_row| split_row.old == Some(change_idx) || split_row.new == Some(change_idx))
+                .unwrap_or(0),
+        }
+    }
 }
 
 impl<'a> Iterator for FileTreeFilesIter<'a> {
@@ -153,17 +544,33 @@ impl<'a> Iterator for FileTreeFilesIter<'a> {
                 },
                 FileTree::File {
                     name,
+                    path,
+                    old_path,
+                    blob_ids,
                     changes,
                     change_kind,
+                    content_kind,
+                    mode_change,
                     hunks,
                     scroll_start,
+                    split_rows,
+                    view,
+                    render_cache,
                 } => {
                     return Some(FileTreeFilesItem {
                         name,
+                        path,
+                        old_path: old_path.as_deref(),
+                        blob_ids,
                         changes,
                         change_kind,
+                        content_kind,
+                        mode_change: *mode_change,
                         hunks,
+                        split_rows,
+                        view: *view,
                         scroll_start: *scroll_start,
+                        render_cache,
                     });
                 }
             }
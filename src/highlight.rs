@@ -0,0 +1,101 @@
+use ratatui::style::Color;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::LazyLock;
+use std::thread;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+
+use crate::state::{Change, ChangeKind};
+
+const THEME_NAME: &str = "base16-ocean.dark";
+
+// loading the bundled grammars takes a while, so it's only done once they're first needed
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static THEME: LazyLock<Theme> = LazyLock::new(|| {
+    let mut themes = ThemeSet::load_defaults().themes;
+    themes.remove(THEME_NAME).unwrap_or_default()
+});
+
+// foreground colors over byte ranges of a change's text
+pub type HighlightedLine = Vec<(Color, Range<usize>)>;
+
+// loads the grammars in the background so the first diff shown doesn't wait on them
+pub fn preload() {
+    thread::spawn(|| {
+        LazyLock::force(&SYNTAXES);
+        LazyLock::force(&THEME);
+    });
+}
+
+/*
+ * Syntax highlights every change in a file, picking the language from the path or, failing
+ * that, a shebang on the first line. The old and new sides are highlighted separately, so
+ * each is parsed as a continuous stream of the lines it has. Returns None for files in a
+ * language without a bundled grammar.
+ * */
+pub fn highlight_file(path: &str, changes: &[Change]) -> Option<Vec<HighlightedLine>> {
+    let syntax = find_syntax(path, changes)?;
+
+    let mut old_side = HighlightLines::new(syntax, &THEME);
+    let mut new_side = HighlightLines::new(syntax, &THEME);
+
+    let highlighted = changes
+        .iter()
+        .map(|change| {
+            let text = change.text.as_str();
+
+            let regions = match change.kind {
+                ChangeKind::HunkHeader | ChangeKind::Comment => return Vec::new(),
+                ChangeKind::Deletion => old_side.highlight_line(text, &SYNTAXES),
+                ChangeKind::Insertion => new_side.highlight_line(text, &SYNTAXES),
+                ChangeKind::Context => {
+                    // both sides need to see context lines to keep their parse state in step
+                    let _ = old_side.highlight_line(text, &SYNTAXES);
+                    new_side.highlight_line(text, &SYNTAXES)
+                },
+            };
+
+            let mut start = 0;
+
+            regions
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(style, region)| {
+                    let range = start..start + region.len();
+                    start = range.end;
+
+                    (Color::Rgb(style.foreground.r, style.foreground.g, style.foreground.b), range)
+                })
+                .collect()
+        })
+        .collect();
+
+    Some(highlighted)
+}
+
+fn find_syntax(path: &str, changes: &[Change]) -> Option<&'static SyntaxReference> {
+    let path = Path::new(path);
+
+    let by_extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(|extension| SYNTAXES.find_syntax_by_extension(extension));
+
+    // grammars list some whole file names, such as `Makefile`, as extensions
+    let by_name = || path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| SYNTAXES.find_syntax_by_extension(name));
+
+    let by_first_line = || changes
+        .iter()
+        .find(|change| change.new_lineno == Some(1) || change.old_lineno == Some(1))
+        .and_then(|change| SYNTAXES.find_syntax_by_first_line(change.text.as_str()));
+
+    by_extension
+        .or_else(by_name)
+        .or_else(by_first_line)
+        .filter(|syntax| syntax.name != "Plain Text")
+}
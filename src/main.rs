@@ -1,25 +1,112 @@
 mod app;
+mod commit_filter;
+mod context;
+mod diff_settings;
+mod editor;
+mod export;
 mod file_tree;
+mod highlight;
 mod repo;
+mod review;
+mod search;
+mod signature;
 mod state;
 mod ui;
+mod verdict;
+mod viewed;
+mod word_diff;
+mod worker;
 
-use clap::Parser;
+use clap::{ArgGroup, Parser, Subcommand};
+use encoding_rs::Encoding;
 use std::env;
 use std::fs::File;
 use std::io;
+use std::fs;
+use std::path::{Path, PathBuf};
 use tracing_subscriber::{filter::EnvFilter, fmt::{self, writer::BoxMakeWriter}, layer::SubscriberExt, util::SubscriberInitExt};
 
-use app::App;
+use app::{App, AppError};
+use diff_settings::{DiffAlgorithm, DiffSettings};
+use export::ExportFormat;
 use repo::Repo;
+use review::Review;
+use signature::SignatureVerifier;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
-struct Args {
-    #[arg(short, long)]
-    from: String,
-    #[arg(short, long)]
-    into: String,
+#[command(group(ArgGroup::new("source").required(true).multiple(true).args(["from", "staged", "unstaged"])))]
+pub struct Args {
+    #[arg(short, long, requires = "into")]
+    pub from: Option<String>,
+    #[arg(short, long, requires = "from")]
+    pub into: Option<String>,
+    /// Also show the net diff of the branch against its merge-base with `into`
+    #[arg(short, long, requires = "from")]
+    pub merge_base: bool,
+    /// Show changes staged in the index (HEAD -> index)
+    #[arg(long)]
+    pub staged: bool,
+    /// Show changes in the working tree not yet staged (index -> working tree)
+    #[arg(long)]
+    pub unstaged: bool,
+    /// Similarity percentage at which a deleted and an added file are treated as a rename or copy [default: 50]
+    #[arg(long, value_parser = clap::value_parser!(u16).range(0..=100))]
+    pub rename_threshold: Option<u16>,
+    /// Number of context lines shown around each change [default: 3]
+    #[arg(short = 'U', long)]
+    pub unified: Option<u32>,
+    /// Ignore whitespace when comparing lines
+    #[arg(short = 'w', long)]
+    pub ignore_all_space: bool,
+    /// Ignore changes in the amount of whitespace
+    #[arg(short = 'b', long)]
+    pub ignore_space_change: bool,
+    /// Ignore changes whose lines are all blank
+    #[arg(long)]
+    pub ignore_blank_lines: bool,
+    /// Diff algorithm to use [default: myers]
+    #[arg(long, value_enum)]
+    pub diff_algorithm: Option<DiffAlgorithm>,
+    /// Shift hunk boundaries to make diffs easier to read
+    #[arg(long, overrides_with = "no_indent_heuristic")]
+    pub indent_heuristic: bool,
+    /// Don't shift hunk boundaries, showing hunks where the diff algorithm put them
+    #[arg(long, overrides_with = "indent_heuristic")]
+    pub no_indent_heuristic: bool,
+    /// Only show changes to paths matching this pathspec, can be given more than once
+    #[arg(short, long = "path", value_name = "PATHSPEC")]
+    pub paths: Vec<String>,
+    /// Hide changes to paths matching this pathspec, can be given more than once
+    #[arg(short = 'x', long = "exclude", value_name = "PATHSPEC")]
+    pub excludes: Vec<String>,
+    /// Number of commits either side of the selected one whose diffs are computed ahead of time
+    #[arg(long, default_value_t = 1)]
+    pub prefetch: usize,
+    /// Encoding used to decode lines that aren't valid UTF-8 when no `working-tree-encoding` attribute applies
+    #[arg(long, default_value = "utf-8", value_parser = parse_encoding)]
+    pub fallback_encoding: &'static Encoding,
+    /// GPG keyring to verify commit signatures against instead of the default one
+    #[arg(long, value_name = "PATH")]
+    pub gpg_keyring: Option<PathBuf>,
+    /// SSH allowed signers file to verify commit signatures against [default: gpg.ssh.allowedSignersFile]
+    #[arg(long, value_name = "PATH")]
+    pub allowed_signers: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Write the review of the commits as a document instead of opening the viewer
+    Export {
+        /// Format of the document [default: json for a `.json` output, markdown otherwise]
+        #[arg(long, value_enum)]
+        format: Option<ExportFormat>,
+        /// File to write the document to instead of standard output
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
 }
 
 fn main() -> io::Result<()> {
@@ -29,13 +116,21 @@ fn main() -> io::Result<()> {
 
     let args = Args::parse();
 
-    let repo = match Repo::new() {
+    let repo = match Repo::new(args.fallback_encoding, args.pathspecs()) {
         Ok(r) => r,
         Err(e) => panic!("Couldn't open repository: {}", e),
     };
 
+    if let Some(Command::Export { format, output }) = &args.command {
+        if let Err(e) = export_review(repo, &args, *format, output.as_deref()) {
+            panic!("Couldn't export the review: {e}");
+        }
+
+        return Ok(());
+    }
+
     let mut terminal = ratatui::init();
-    let mut app = match App::new(repo, args.from, args.into) {
+    let mut app = match App::new(repo, args) {
         Ok(a) => a,
         Err(e) => panic!("{e}"),
     };
@@ -47,6 +142,95 @@ fn main() -> io::Result<()> {
     result
 }
 
+fn export_review(mut repository: Repo, args: &Args, format: Option<ExportFormat>, output: Option<&Path>) -> Result<(), AppError> {
+    let mut diff_settings = DiffSettings::from_config(&repository.config()?);
+    args.apply_diff_settings(&mut diff_settings);
+    repository.set_diff_settings(diff_settings);
+
+    let (commits, commits_order) = app::load_commits(&repository, args)?;
+
+    let review = Review::load(repository.git_dir()).map_err(repo::RepoError::Io)?;
+    let title = export::review_title(args.from.as_deref(), args.into.as_deref());
+    let format = format.unwrap_or_else(|| output.map_or(ExportFormat::Markdown, ExportFormat::for_path));
+
+    let commits: Vec<_> = commits_order.iter().map(|hash| &commits[hash]).collect();
+    let document = export::export(&repository, title.as_str(), &commits, &review, format)?;
+
+    match output {
+        Some(path) => fs::write(path, document).map_err(repo::RepoError::Io)?,
+        None => print!("{document}"),
+    }
+
+    Ok(())
+}
+
+impl Args {
+    // flags take precedence over values read from git config
+    pub fn apply_diff_settings(&self, settings: &mut DiffSettings) {
+        if let Some(threshold) = self.rename_threshold {
+            settings.rename_threshold = threshold;
+        }
+
+        if let Some(lines) = self.unified {
+            settings.context_lines = lines;
+        }
+
+        settings.ignore_whitespace |= self.ignore_all_space;
+        settings.ignore_whitespace_change |= self.ignore_space_change;
+        settings.ignore_blank_lines |= self.ignore_blank_lines;
+
+        if let Some(algorithm) = self.diff_algorithm {
+            settings.algorithm = algorithm;
+        }
+
+        if self.indent_heuristic {
+            settings.indent_heuristic = true;
+        } else if self.no_indent_heuristic {
+            settings.indent_heuristic = false;
+        }
+    }
+
+    pub fn apply_signature_settings(&self, verifier: &mut SignatureVerifier) {
+        if let Some(keyring) = &self.gpg_keyring {
+            verifier.gpg_keyring = Some(keyring.clone());
+        }
+
+        if let Some(allowed_signers) = &self.allowed_signers {
+            verifier.allowed_signers = Some(allowed_signers.clone());
+        }
+    }
+
+    /*
+     * libgit2 uses the first pattern that matches a path, and negates patterns starting
+     * with `!`, so exclusions go first. Git's `:(exclude)` and `:!` magic are accepted too.
+     * */
+    pub fn pathspecs(&self) -> Vec<String> {
+        let mut pathspecs: Vec<String> = self.excludes
+            .iter()
+            .map(|exclude| {
+                let pattern = exclude
+                    .strip_prefix(":(exclude)")
+                    .or_else(|| exclude.strip_prefix(":!"))
+                    .or_else(|| exclude.strip_prefix(":^"))
+                    .unwrap_or(exclude);
+
+                format!("!{pattern}")
+            })
+            .collect();
+
+        if self.paths.is_empty() && !pathspecs.is_empty() {
+            pathspecs.push(String::from("*"));
+        }
+
+        pathspecs.extend(self.paths.iter().cloned());
+        pathspecs
+    }
+}
+
+fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.as_bytes()).ok_or_else(|| format!("unknown encoding `{label}`"))
+}
+
 fn init_logging() {
     let file = File::create("debug.log").expect("could not create log file");
 
@@ -59,3 +243,36 @@ fn init_logging() {
         )
         .init();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pathspecs(args: &[&str]) -> Vec<String> {
+        let args = ["branch-diff", "-i", "main", "-f", "feature"].iter().chain(args);
+        Args::parse_from(args).pathspecs()
+    }
+
+    #[test]
+    fn excludes_go_first() {
+        assert_eq!(pathspecs(&["-p", "src", "-x", "src/vendor", "-p", "docs"]), ["!src/vendor", "src", "docs"]);
+    }
+
+    #[test]
+    fn exclude_magic_is_stripped() {
+        assert_eq!(
+            pathspecs(&["-p", "src", "-x", ":(exclude)a", "-x", ":!b", "-x", ":^c"]),
+            ["!a", "!b", "!c", "src"],
+        );
+    }
+
+    #[test]
+    fn only_excludes_match_everything_else() {
+        assert_eq!(pathspecs(&["-x", "Cargo.lock"]), ["!Cargo.lock", "*"]);
+    }
+
+    #[test]
+    fn no_paths_means_no_pathspecs() {
+        assert!(pathspecs(&[]).is_empty());
+    }
+}
@@ -1,26 +1,46 @@
-use git2::{DiffFormat, Repository};
+use encoding_rs::Encoding;
+use git2::{AttrCheckFlags, AttrValue, Delta, DiffDelta, DiffFile, DiffFindOptions, DiffHunk, DiffOptions, FileMode, Oid, Patch, Repository};
 use std::collections::HashMap;
 use std::env;
 use std::fmt;
 use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
+use crate::context::SourceLine;
+use crate::diff_settings::DiffSettings;
+use crate::file_tree::BlobIds;
+use crate::file_tree::DiffView;
 use crate::file_tree::FileChangeKind;
+use crate::file_tree::FileContentKind;
 use crate::file_tree::FileTree;
-use crate::file_tree::FileTreeFilesItem;
+use crate::signature::{SignatureStatus, SignatureVerifier};
 use crate::state::Change;
 use crate::state::ChangeKind;
 use crate::state::Commit;
+use crate::state::CommitKind;
+use crate::state::Identity;
+use crate::state::MergeDiff;
+use crate::verdict::{self, NOTES_REF, Verdict, VerdictKind};
 
 pub struct Repo {
     repository: Repository,
+    diff_settings: DiffSettings,
+    pathspecs: Vec<String>,
+    fallback_encoding: &'static Encoding,
 }
 
 impl Repo {
-    pub fn new() -> Result<Self, RepoError> {
+    pub fn new(fallback_encoding: &'static Encoding, pathspecs: Vec<String>) -> Result<Self, RepoError> {
         let path = env::current_dir()?;
         let repository = Repository::open(path)?;
 
-        Ok(Repo { repository })
+        Ok(Repo {
+            repository,
+            diff_settings: DiffSettings::default(),
+            pathspecs,
+            fallback_encoding,
+        })
     }
 
     pub fn commits_in_range(&self, base: &str, head: &str) -> Result<(HashMap<String, Commit>, Vec<String>), RepoError> {
@@ -39,47 +59,388 @@ impl Repo {
         for oid in revwalk {
             let commit = self.repository.find_commit(oid?)?;
             let hash = commit.id().to_string();
-            let author = commit.author().to_string();
-            let message = match commit.message() {
-                Some(msg) => Some(msg.to_string()),
-                None => None,
+            let short_hash = self.short_hash(commit.id())?;
+            let message = commit.message().map(|msg| msg.to_string());
+
+            let author = Repo::identity(&commit.author());
+            let committer = Some(Repo::identity(&commit.committer())).filter(|committer| *committer != author);
+
+            let parents = commit.parent_ids().map(|id| id.to_string()).collect();
+
+            let trailers = match message.as_deref().map(git2::message_trailers_strs) {
+                Some(Ok(trailers)) => trailers
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+                _ => Vec::new(),
             };
 
-            match self.get_commit_diff(commit) {
-                Err(e) => {
-                    return Err(e);
-                },
-                Ok(file_tree) => {
-                    let diff_len = file_tree
-                        .iter_files()
-                        .map(|FileTreeFilesItem { changes, .. }| { changes.len() })
-                        .sum();
-
-                    commits_order.push(hash.clone());
-                    commits.insert(hash.clone(), Commit {
-                        hash,
-                        message,
-                        author,
-                        file_tree,
-                        diff_len,
-                    });
-                },
-            }
+            // diffs are computed on demand when each commit is first selected
+            commits_order.push(hash.clone());
+            commits.insert(hash.clone(), Commit {
+                hash,
+                short_hash,
+                message,
+                author: Some(author),
+                committer,
+                parents,
+                trailers,
+                kind: CommitKind::Commit,
+                merge_diff: MergeDiff::FirstParent,
+                signature: None,
+                verdicts: Vec::new(),
+                diff_settings: self.diff_settings,
+                file_tree: None,
+                diff_len: 0,
+                outside_paths: false,
+            });
         }
 
         Ok((commits, commits_order))
     }
 
-    fn get_commit_diff(&self, commit: git2::Commit) -> Result<FileTree, RepoError> {
+    /*
+     * Builds a single pseudo-commit holding the cumulative diff of `head` against its merge-base
+     * with `base`, i.e. the net change a pull request from `head` into `base` would show.
+     * */
+    pub fn whole_branch(&self, base: &str, head: &str) -> Result<Commit, RepoError> {
+        let base_commit = self.repository.revparse_single(base)?.peel_to_commit()?;
+        let head_commit = self.repository.revparse_single(head)?.peel_to_commit()?;
+
+        let merge_base = self.repository.merge_base(base_commit.id(), head_commit.id())?;
+
+        Ok(self.pseudo_commit(
+            format!("{}..{}", merge_base, head_commit.id()),
+            format!("{}..{}", self.short_hash(merge_base)?, self.short_hash(head_commit.id())?),
+            "Whole branch",
+            CommitKind::WholeBranch,
+        ))
+    }
+
+    pub fn staged_changes(&self) -> Commit {
+        let hash = String::from("HEAD..index");
+        self.pseudo_commit(hash.clone(), hash, "Staged changes", CommitKind::Staged)
+    }
+
+    pub fn unstaged_changes(&self) -> Commit {
+        let hash = String::from("index..workdir");
+        self.pseudo_commit(hash.clone(), hash, "Unstaged changes", CommitKind::Unstaged)
+    }
+
+    fn pseudo_commit(&self, hash: String, short_hash: String, message: &str, kind: CommitKind) -> Commit {
+        Commit {
+            hash,
+            short_hash,
+            message: Some(message.to_string()),
+            author: None,
+            committer: None,
+            parents: Vec::new(),
+            trailers: Vec::new(),
+            kind,
+            merge_diff: MergeDiff::FirstParent,
+            signature: None,
+            verdicts: Vec::new(),
+            diff_settings: self.diff_settings,
+            file_tree: None,
+            diff_len: 0,
+            outside_paths: false,
+        }
+    }
+
+    pub fn verify_signature(&self, hash: &str, verifier: &SignatureVerifier) -> Result<SignatureStatus, RepoError> {
+        let oid = Oid::from_str(hash)?;
+
+        let (signature, signed_data) = match self.repository.extract_signature(&oid, None) {
+            Ok(extracted) => extracted,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(SignatureStatus::Unsigned),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(verifier.verify(&signature, &signed_data)?)
+    }
+
+    // every verdict left on a commit, by the commit's hash
+    pub fn verdicts(&self) -> Result<HashMap<String, Vec<Verdict>>, RepoError> {
+        let notes = match self.repository.notes(Some(NOTES_REF)) {
+            Ok(notes) => notes,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut verdicts = HashMap::new();
+
+        for ids in notes {
+            let (_, commit_id) = ids?;
+            let note = self.repository.find_note(Some(NOTES_REF), commit_id)?;
+
+            verdicts.insert(commit_id.to_string(), verdict::parse_note(note.message().unwrap_or_default()));
+        }
+
+        Ok(verdicts)
+    }
+
+    /*
+     * Replaces the verdict the configured `user.email` has left on a commit, or takes it back
+     * when `kind` is None, leaving everyone else's as they are. Returns the commit's verdicts
+     * as they now stand.
+     * */
+    pub fn set_verdict(&self, hash: &str, kind: Option<VerdictKind>, note: &str) -> Result<Vec<Verdict>, RepoError> {
+        let oid = Oid::from_str(hash)?;
+        let reviewer = self.repository.signature()?;
+        let email = reviewer.email().unwrap_or_default().to_string();
+
+        let mut verdicts = match self.repository.find_note(Some(NOTES_REF), oid) {
+            Ok(note) => verdict::parse_note(note.message().unwrap_or_default()),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        verdicts.retain(|verdict| verdict.email != email);
+
+        if let Some(kind) = kind {
+            verdicts.push(Verdict {
+                kind,
+                name: reviewer.name().unwrap_or_default().to_string(),
+                email,
+                note: note.to_string(),
+            });
+        }
+
+        if verdicts.is_empty() {
+            match self.repository.note_delete(oid, Some(NOTES_REF), &reviewer, &reviewer) {
+                Err(e) if e.code() != git2::ErrorCode::NotFound => return Err(e.into()),
+                _ => {},
+            }
+        } else {
+            self.repository.note(&reviewer, &reviewer, Some(NOTES_REF), oid, verdict::format_note(&verdicts).as_str(), true)?;
+        }
+
+        Ok(verdicts)
+    }
+
+    // the email verdicts are left under, from git's `user.email`
+    pub fn reviewer_email(&self) -> Result<String, RepoError> {
+        Ok(self.repository.signature()?.email().unwrap_or_default().to_string())
+    }
+
+    /*
+     * Whether a commit's changes touch a path containing each of `paths`, and add or remove a
+     * line containing each of `pickaxes`. The changes are those of the commit's diff as first
+     * shown, limited to the paths given on the command line and with the same diff options,
+     * so merges are searched in their diff against their first parent only.
+     * */
+    pub fn commit_matches(&self, hash: &str, paths: &[String], pickaxes: &[String]) -> Result<bool, RepoError> {
+        let commit = self.repository.find_commit(Oid::from_str(hash)?)?;
+
+        let parent_tree = match commit.parent_count() {
+            0 => None,
+            _ => Some(commit.parent(0)?.tree()?),
+        };
+
+        let mut opts = self.diff_options();
+        let diff = self.repository.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), Some(&mut opts))?;
+
+        let touched: Vec<String> = diff
+            .deltas()
+            .flat_map(|delta| [delta.old_file().path(), delta.new_file().path()])
+            .flatten()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+
+        if !paths.iter().all(|path| touched.iter().any(|touched| touched.contains(path.as_str()))) {
+            return Ok(false);
+        }
+
+        let mut unmatched: Vec<&String> = pickaxes.iter().collect();
+
+        if unmatched.is_empty() {
+            return Ok(true);
+        }
+
+        let searched = diff.foreach(
+            &mut |_, _| true,
+            None,
+            None,
+            Some(&mut |_, _, line| {
+                if matches!(line.origin(), '+' | '-') {
+                    let content = String::from_utf8_lossy(line.content());
+                    unmatched.retain(|pickaxe| !content.contains(pickaxe.as_str()));
+                }
+
+                // stop walking the diff once everything has been found
+                !unmatched.is_empty()
+            }),
+        );
+
+        match searched {
+            Err(e) if e.code() != git2::ErrorCode::User => Err(e.into()),
+            _ => Ok(unmatched.is_empty()),
+        }
+    }
+
+    /*
+     * Whether any of a commit's changes against its first parent are in the paths given on
+     * the command line. Only the changed files are listed, not their contents, so this is
+     * cheap enough to run over the whole range before any diffs are computed.
+     * */
+    pub fn touches_pathspecs(&self, hash: &str) -> Result<bool, RepoError> {
+        let commit = self.repository.find_commit(Oid::from_str(hash)?)?;
+
+        let parent_tree = match commit.parent_count() {
+            0 => None,
+            _ => Some(commit.parent(0)?.tree()?),
+        };
+
+        let mut opts = DiffOptions::new();
+
+        for pathspec in &self.pathspecs {
+            opts.pathspec(pathspec);
+        }
+
+        let diff = self.repository.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), Some(&mut opts))?;
+
+        Ok(diff.deltas().len() > 0)
+    }
+
+    // shortest abbreviation that is unique within the repository
+    fn short_hash(&self, oid: Oid) -> Result<String, RepoError> {
+        let short_id = self.repository.find_object(oid, None)?.short_id()?;
+
+        Ok(short_id.as_str().unwrap_or_default().to_string())
+    }
+
+    fn identity(signature: &git2::Signature) -> Identity {
+        Identity {
+            name: signature.name().unwrap_or_default().to_string(),
+            email: signature.email().unwrap_or_default().to_string(),
+            time: signature.when().seconds(),
+            offset_minutes: signature.when().offset_minutes(),
+        }
+    }
+
+    pub fn set_diff_settings(&mut self, diff_settings: DiffSettings) {
+        self.diff_settings = diff_settings;
+    }
+
+    // the `.git` directory, shared by all of the repository's worktrees
+    pub fn git_dir(&self) -> &Path {
+        self.repository.commondir()
+    }
+
+    pub fn config(&self) -> Result<git2::Config, RepoError> {
+        Ok(self.repository.config()?)
+    }
+
+    /*
+     * Recomputes the diff of any entry in the commits pane with the current diff settings.
+     * For the whole branch entry, `hash` holds the merge-base and head ids separated by `..`.
+     * */
+    pub fn commit_diff(&self, hash: &str, kind: CommitKind, merge_diff: MergeDiff) -> Result<FileTree, RepoError> {
+        match kind {
+            CommitKind::Commit => {
+                let commit = self.repository.find_commit(Oid::from_str(hash)?)?;
+                self.get_commit_diff(&commit, merge_diff)
+            },
+            CommitKind::WholeBranch => {
+                let (merge_base, head) = hash
+                    .split_once("..")
+                    .ok_or_else(|| git2::Error::from_str("malformed whole branch range"))?;
+
+                self.whole_branch_diff(Oid::from_str(merge_base)?, Oid::from_str(head)?)
+            },
+            CommitKind::Staged => self.staged_diff(),
+            CommitKind::Unstaged => self.unstaged_diff(),
+        }
+    }
+
+    fn whole_branch_diff(&self, merge_base: Oid, head: Oid) -> Result<FileTree, RepoError> {
+        let merge_base_tree = self.repository.find_commit(merge_base)?.tree()?;
+        let head_tree = self.repository.find_commit(head)?.tree()?;
+
+        let mut opts = self.diff_options();
+        let mut diff = self.repository.diff_tree_to_tree(Some(&merge_base_tree), Some(&head_tree), Some(&mut opts))?;
+
+        self.diff_to_file_tree(&mut diff, None)
+    }
+
+    fn staged_diff(&self) -> Result<FileTree, RepoError> {
+        // HEAD is unborn in a fresh repository, in which case everything in the index is new
+        let head_tree = self.repository.head().ok().and_then(|head| head.peel_to_tree().ok());
+
+        let mut opts = self.diff_options();
+        let mut diff = self.repository.diff_tree_to_index(head_tree.as_ref(), None, Some(&mut opts))?;
+
+        self.diff_to_file_tree(&mut diff, None)
+    }
+
+    fn unstaged_diff(&self) -> Result<FileTree, RepoError> {
+        let mut opts = self.diff_options();
+        opts.include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .show_untracked_content(true);
+
+        let mut diff = self.repository.diff_index_to_workdir(None, Some(&mut opts))?;
+
+        self.diff_to_file_tree(&mut diff, None)
+    }
+
+    fn get_commit_diff(&self, commit: &git2::Commit, merge_diff: MergeDiff) -> Result<FileTree, RepoError> {
         let tree = commit.tree()?;
 
-        let parent_tree = if commit.parent_count() > 0 {
-            Some(commit.parent(0)?.tree()?)
+        let parent_idx = match merge_diff {
+            MergeDiff::Parent(idx) if idx < commit.parent_count() => idx,
+            MergeDiff::AllParents | MergeDiff::OverlappingHunks if commit.parent_count() > 1 => {
+                let mut diffs = Vec::new();
+
+                for idx in 0..commit.parent_count() {
+                    let parent_tree = commit.parent(idx)?.tree()?;
+                    let mut opts = self.diff_options();
+                    diffs.push(self.repository.diff_tree_to_tree(Some(&parent_tree), Some(&tree), Some(&mut opts))?);
+                }
+
+                let mut first_parent_diff = diffs.remove(0);
+                let filter = MergeFilter::new(&diffs, merge_diff == MergeDiff::OverlappingHunks)?;
+
+                return self.diff_to_file_tree(&mut first_parent_diff, Some(&filter));
+            },
+            _ => 0,
+        };
+
+        let parent_tree = if commit.parent_count() > parent_idx {
+            Some(commit.parent(parent_idx)?.tree()?)
         } else {
             None
         };
 
-        let diff = self.repository.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+        let mut opts = self.diff_options();
+        let mut diff = self.repository.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut opts))?;
+
+        self.diff_to_file_tree(&mut diff, None)
+    }
+
+    fn diff_options(&self) -> DiffOptions {
+        let mut opts = self.diff_settings.diff_options();
+
+        for pathspec in &self.pathspecs {
+            opts.pathspec(pathspec);
+        }
+
+        opts
+    }
+
+    fn find_similar(&self, diff: &mut git2::Diff) -> Result<(), RepoError> {
+        let mut find_opts = DiffFindOptions::new();
+        find_opts
+            .renames(true)
+            .copies(true)
+            .rename_threshold(self.diff_settings.rename_threshold)
+            .copy_threshold(self.diff_settings.rename_threshold);
+
+        Ok(diff.find_similar(Some(&mut find_opts))?)
+    }
+
+    fn diff_to_file_tree(&self, diff: &mut git2::Diff, filter: Option<&MergeFilter>) -> Result<FileTree, RepoError> {
+        self.find_similar(diff)?;
 
         let root_dir = match self.repository.workdir() {
             Some(dir) => dir
@@ -90,116 +451,333 @@ impl Repo {
         };
 
         let mut file_tree = FileTree::new(root_dir);
-        let mut previous_file_path: Option<String> = None;
-        let mut previous_file_diff: Vec<Change> = Vec::new();
-        let mut previous_hunk_start: Option<u32> = None;
-        let mut hunks: Vec<usize> = Vec::new();
-        let mut previous_change_kind: Option<FileChangeKind> = None;
-        let mut next_scroll_start: usize = 0;
-        let mut line_count: usize = 0;
-
-        let result = diff.print(DiffFormat::Patch, |delta, hunk, line| {
-            let Ok(text) = std::str::from_utf8(line.content()) else {
-                return true;
+
+        for delta_idx in 0..diff.deltas().len() {
+            let Some(patch) = Patch::from_diff(diff, delta_idx)? else {
+                continue;
             };
 
-            if let Some(h) = hunk {
-                if previous_hunk_start.map_or(true, |start| h.new_start() != start) {
-                    hunks.push(previous_file_diff.len());
-                    previous_hunk_start = Some(h.new_start());
-                }
-            }
+            let delta = patch.delta();
 
-            let change_kind = match line.origin() {
-                ' ' => ChangeKind::Context,
-                '+' => ChangeKind::Insertion,
-                '-' => ChangeKind::Deletion,
-                _   => {
-                    return true;
-                },
+            let change_kind = match delta.status() {
+                Delta::Added | Delta::Untracked => FileChangeKind::Creation,
+                Delta::Deleted => FileChangeKind::Deletion,
+                Delta::Renamed => FileChangeKind::Rename,
+                Delta::Copied => FileChangeKind::Copy,
+                _ => FileChangeKind::Change,
             };
 
-            let Some(file_path) = ({
-                let path = match change_kind {
-                    ChangeKind::Insertion | ChangeKind::Context => delta.new_file().path(),
-                    ChangeKind::Deletion => delta.old_file().path(),
-                };
+            let path = match change_kind {
+                FileChangeKind::Deletion => delta.old_file().path(),
+                _ => delta.new_file().path(),
+            };
 
-                if let Some(p) = path {
-                    p.to_str()
-                } else {
-                    None
-                }
-            }) else {
-                println!("Couldn't get file path for diff delta");
-                return true;
+            let Some(path) = path.and_then(|p| p.to_str()) else {
+                tracing::error!("couldn't get file path for diff delta");
+                continue;
             };
 
-            if let Some(cfp) = &previous_file_path && file_path != cfp {
-                file_tree.insert_file(
-                    cfp.as_str(),
-                    std::mem::take(&mut previous_file_diff),
-                    previous_change_kind.expect("previous_change_kind was None when trying to insert file"),
-                    std::mem::take(&mut hunks),
-                    next_scroll_start,
-                );
-
-                previous_file_diff.clear();
-                hunks.clear();
-                previous_change_kind = None;
-                next_scroll_start = line_count;
+            if let Some(filter) = filter && !filter.keeps_file(path) {
+                continue;
             }
 
-            previous_file_path = Some(file_path.to_string());
-            previous_change_kind = Some(Repo::update_file_change_kind(previous_change_kind, change_kind));
-            line_count += 1;
+            let old_path = match change_kind {
+                FileChangeKind::Rename | FileChangeKind::Copy => delta
+                    .old_file()
+                    .path()
+                    .and_then(|p| p.to_str())
+                    .map(|p| p.to_string()),
+                _ => None,
+            };
+
+            let encoding = self.file_encoding(path);
 
-            let change = Change {
-                text: text.to_string(),
-                kind: change_kind,
+            let blob_ids = BlobIds {
+                old: Repo::blob_id(&delta.old_file()),
+                new: Repo::blob_id(&delta.new_file()),
             };
 
-            previous_file_diff.push(change);
+            let mut changes: Vec<Change> = Vec::new();
 
-            true
-        });
+            let mut hunks_dropped = false;
 
-        file_tree.insert_file(
-            previous_file_path.expect("previous_file_path was None when trying to insert file").as_str(),
-            previous_file_diff,
-            previous_change_kind.expect("previous_change_kind was None when trying to insert file"),
-            hunks,
-            next_scroll_start,
-        );
+            for hunk_idx in 0..patch.num_hunks() {
+                let (hunk, _) = patch.hunk(hunk_idx)?;
 
-        if let Err(e) = result {
-            Err(RepoError::Git(e))
-        } else {
-            file_tree.sort();
-            Ok(file_tree)
-        }
-    }
-
-    fn update_file_change_kind(file_change_kind: Option<FileChangeKind>, line_change_kind: ChangeKind) -> FileChangeKind {
-        match file_change_kind {
-            Some(kind) => match kind {
-                FileChangeKind::Creation => match line_change_kind {
-                    ChangeKind::Context | ChangeKind::Deletion => FileChangeKind::Change,
-                    ChangeKind::Insertion => FileChangeKind::Creation,
-                },
-                FileChangeKind::Deletion => match line_change_kind {
-                    ChangeKind::Context | ChangeKind::Insertion => FileChangeKind::Change,
-                    ChangeKind::Deletion => FileChangeKind::Deletion,
-                },
-                FileChangeKind::Change => FileChangeKind::Change,
+                if let Some(filter) = filter && !filter.keeps_hunk(path, &hunk) {
+                    hunks_dropped = true;
+                    continue;
+                }
+
+                // the `@@ -a,b +c,d @@` range, followed by the enclosing function if git found one
+                changes.push(Change {
+                    text: String::from_utf8_lossy(hunk.header()).trim_end().to_string(),
+                    kind: ChangeKind::HunkHeader,
+                    encoding: None,
+                    old_lineno: None,
+                    new_lineno: None,
+                });
+
+                for line_idx in 0..patch.num_lines_in_hunk(hunk_idx)? {
+                    let line = patch.line_in_hunk(hunk_idx, line_idx)?;
+
+                    let kind = match line.origin() {
+                        ' ' => ChangeKind::Context,
+                        '+' => ChangeKind::Insertion,
+                        '-' => ChangeKind::Deletion,
+                        _   => continue,
+                    };
+
+                    let SourceLine { text, encoding } = Repo::decode_line(line.content(), encoding);
+
+                    changes.push(Change {
+                        text,
+                        kind,
+                        encoding,
+                        old_lineno: line.old_lineno(),
+                        new_lineno: line.new_lineno(),
+                    });
+                }
+            }
+
+            // every hunk of this file matched one of the other parents, so there's nothing to show
+            if changes.is_empty() && hunks_dropped {
+                continue;
+            }
+
+            let content_kind = self.file_content_kind(&delta, change_kind, &changes);
+
+            if content_kind != FileContentKind::Text {
+                changes.clear();
+            }
+
+            // a file with only its mode changed says so in place of its content instead
+            let mode_change = Repo::mode_change(&delta).filter(|_| !matches!(content_kind, FileContentKind::ModeChange { .. }));
+
+            file_tree.insert_file(path, old_path, blob_ids, changes, change_kind, content_kind, mode_change);
+        }
+
+        file_tree.sort();
+        file_tree.layout(DiffView::Unified);
+
+        Ok(file_tree)
+    }
+
+    /*
+     * Reads one side of a file in a diff line by line, for showing more of it than the diff's
+     * context lines. Working tree files have no blob yet, so those are read from disk.
+     * */
+    pub fn file_lines(&self, path: &str, blob_id: Option<&str>) -> Result<Vec<SourceLine>, RepoError> {
+        let Some(blob_id) = blob_id else {
+            return Ok(Vec::new());
+        };
+
+        let content = self.file_content(path, blob_id)?;
+        let encoding = self.file_encoding(path);
+
+        Ok(content
+            .split_inclusive(|&byte| byte == b'\n')
+            .map(|line| Repo::decode_line(line, encoding))
+            .collect())
+    }
+
+    // the blob's bytes, or the working tree file's for changes git hasn't stored a blob for
+    pub fn file_content(&self, path: &str, blob_id: &str) -> Result<Vec<u8>, RepoError> {
+        match self.repository.find_blob(Oid::from_str(blob_id)?) {
+            Ok(blob) => Ok(blob.content().to_vec()),
+            Err(_) => {
+                let path = self
+                    .workdir_file(path)
+                    .ok_or_else(|| git2::Error::from_str("repository has no working tree"))?;
+
+                Ok(std::fs::read(path)?)
             },
-            None => match line_change_kind {
-                ChangeKind::Context => FileChangeKind::Change,
-                ChangeKind::Insertion => FileChangeKind::Creation,
-                ChangeKind::Deletion => FileChangeKind::Deletion,
+        }
+    }
+
+    // None for bare repositories
+    pub fn workdir_file(&self, path: &str) -> Option<PathBuf> {
+        Some(self.repository.workdir()?.join(path))
+    }
+
+    fn blob_id(file: &DiffFile) -> Option<String> {
+        file.exists().then(|| file.id().to_string())
+    }
+
+    fn decode_line(content: &[u8], encoding: &'static Encoding) -> SourceLine {
+        match std::str::from_utf8(content) {
+            Ok(text) => SourceLine { text: text.to_string(), encoding: None },
+            Err(_) => {
+                let (text, _) = encoding.decode_without_bom_handling(content);
+                SourceLine { text: text.into_owned(), encoding: Some(encoding.name()) }
             },
         }
     }
+
+    /*
+     * Lines that aren't valid UTF-8 are decoded with the path's `working-tree-encoding`
+     * attribute if it has one, or with the configured fallback encoding otherwise.
+     * */
+    fn file_encoding(&self, path: &str) -> &'static Encoding {
+        let attr = self.repository.get_attr(Path::new(path), "working-tree-encoding", AttrCheckFlags::FILE_THEN_INDEX);
+
+        match attr.map(AttrValue::from_string) {
+            Ok(AttrValue::String(label)) => Encoding::for_label(label.as_bytes()).unwrap_or_else(|| {
+                tracing::error!("unknown working-tree-encoding `{label}` for {path}");
+                self.fallback_encoding
+            }),
+            _ => self.fallback_encoding,
+        }
+    }
+
+    fn mode_change(delta: &DiffDelta) -> Option<(u32, u32)> {
+        let (old_file, new_file) = (delta.old_file(), delta.new_file());
+
+        (old_file.exists() && new_file.exists() && old_file.mode() != new_file.mode())
+            .then(|| (old_file.mode().into(), new_file.mode().into()))
+    }
+
+    fn file_content_kind(&self, delta: &DiffDelta, change_kind: FileChangeKind, changes: &[Change]) -> FileContentKind {
+        let old_file = delta.old_file();
+        let new_file = delta.new_file();
+
+        if old_file.mode() == FileMode::Link || new_file.mode() == FileMode::Link {
+            return FileContentKind::Symlink {
+                old_target: self.symlink_target(&old_file),
+                new_target: self.symlink_target(&new_file),
+            };
+        }
+
+        if delta.flags().is_binary() || old_file.is_binary() || new_file.is_binary() {
+            return FileContentKind::Binary {
+                old_size: self.file_size(&old_file),
+                new_size: self.file_size(&new_file),
+            };
+        }
+
+        if !changes.is_empty() {
+            return FileContentKind::Text;
+        }
+
+        if let Some((old_mode, new_mode)) = Repo::mode_change(delta) {
+            return FileContentKind::ModeChange { old_mode, new_mode };
+        }
+
+        match change_kind {
+            FileChangeKind::Rename | FileChangeKind::Copy => FileContentKind::Unchanged,
+            _ => FileContentKind::Empty,
+        }
+    }
+
+    /*
+     * Files on the working tree side of a diff have no blob in the object database yet, so
+     * these fall back to reading the file itself when the blob can't be found.
+     * */
+    fn file_size(&self, file: &DiffFile) -> u64 {
+        if !file.exists() {
+            return 0;
+        }
+
+        if file.size() > 0 {
+            return file.size();
+        }
+
+        if let Ok(blob) = self.repository.find_blob(file.id()) {
+            return blob.size() as u64;
+        }
+
+        self.workdir_path(file)
+            .and_then(|path| std::fs::symlink_metadata(path).ok())
+            .map_or(0, |metadata| metadata.len())
+    }
+
+    fn symlink_target(&self, file: &DiffFile) -> Option<String> {
+        if !file.exists() {
+            return None;
+        }
+
+        if let Ok(blob) = self.repository.find_blob(file.id()) {
+            return Some(String::from_utf8_lossy(blob.content()).into_owned());
+        }
+
+        self.workdir_path(file)
+            .and_then(|path| std::fs::read_link(path).ok())
+            .map(|target| target.to_string_lossy().into_owned())
+    }
+
+    fn workdir_path(&self, file: &DiffFile) -> Option<PathBuf> {
+        Some(self.repository.workdir()?.join(file.path()?))
+    }
+}
+
+/*
+ * Narrows a merge commit's first-parent diff using its diffs against each of its other
+ * parents. Only files changed relative to every parent are kept. With `hunks` set, hunks are
+ * also dropped unless the lines they touch in the merge result overlap a hunk against every
+ * other parent, which mostly leaves the conflict resolutions and other changes made in the
+ * merge itself. Whole hunks are kept or dropped, so unlike `git diff --cc` lines that match
+ * one of the parents can still show inside a kept hunk.
+ * */
+struct MergeFilter {
+    // per other parent, the new-side line ranges changed in each file
+    other_parents: Vec<HashMap<String, Vec<Range<u32>>>>,
+    hunks: bool,
+}
+
+impl MergeFilter {
+    fn new(other_parent_diffs: &[git2::Diff], hunks: bool) -> Result<Self, RepoError> {
+        let mut other_parents = Vec::new();
+
+        for diff in other_parent_diffs {
+            let mut changed_ranges = HashMap::new();
+
+            for delta_idx in 0..diff.deltas().len() {
+                let Some(patch) = Patch::from_diff(diff, delta_idx)? else {
+                    continue;
+                };
+
+                let delta = patch.delta();
+                let Some(path) = delta.new_file().path().or(delta.old_file().path()).and_then(|p| p.to_str()) else {
+                    continue;
+                };
+
+                let mut ranges = Vec::new();
+                for hunk_idx in 0..patch.num_hunks() {
+                    ranges.push(MergeFilter::hunk_range(&patch.hunk(hunk_idx)?.0));
+                }
+
+                changed_ranges.insert(path.to_string(), ranges);
+            }
+
+            other_parents.push(changed_ranges);
+        }
+
+        Ok(MergeFilter { other_parents, hunks })
+    }
+
+    fn keeps_file(&self, path: &str) -> bool {
+        self.other_parents.iter().all(|changed_ranges| changed_ranges.contains_key(path))
+    }
+
+    fn keeps_hunk(&self, path: &str, hunk: &DiffHunk) -> bool {
+        if !self.hunks {
+            return true;
+        }
+
+        let range = MergeFilter::hunk_range(hunk);
+
+        self.other_parents.iter().all(|changed_ranges| match changed_ranges.get(path) {
+            // binary files have no hunks to compare against
+            Some(ranges) if ranges.is_empty() => true,
+            Some(ranges) => ranges.iter().any(|r| r.start < range.end && range.start < r.end),
+            None => false,
+        })
+    }
+
+    // pure deletions cover no new-side lines, so give them a width of one to still overlap
+    fn hunk_range(hunk: &DiffHunk) -> Range<u32> {
+        hunk.new_start()..hunk.new_start() + hunk.new_lines().max(1)
+    }
 }
 
 impl std::fmt::Debug for Repo {
@@ -246,3 +824,141 @@ impl From<git2::Error> for RepoError {
         RepoError::Git(e)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Signature;
+    use tempfile::TempDir;
+
+    fn lines(edits: &[(usize, &str)]) -> String {
+        (1..=20)
+            .map(|n| edits.iter().find(|(line, _)| *line == n).map_or(format!("l{}\n", n), |(_, text)| format!("{}\n", text)))
+            .collect()
+    }
+
+    fn commit(repository: &Repository, parents: &[Oid], files: &[(&str, String)]) -> Oid {
+        let mut builder = repository.treebuilder(None).unwrap();
+        for (path, content) in files {
+            let blob = repository.blob(content.as_bytes()).unwrap();
+            builder.insert(path, blob, 0o100644).unwrap();
+        }
+
+        let tree = repository.find_tree(builder.write().unwrap()).unwrap();
+        let parents: Vec<_> = parents.iter().map(|id| repository.find_commit(*id).unwrap()).collect();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+
+        repository
+            .commit(None, &signature, &signature, "commit", &tree, &parents.iter().collect::<Vec<_>>())
+            .unwrap()
+    }
+
+    /*
+     * Both sides change line 2 of a.txt, which the merge resolves by hand, while line 18 is
+     * taken from the second parent. b.txt and c.txt are each changed on one side only.
+     * */
+    fn conflicted_merge() -> (TempDir, Repo, Oid) {
+        let dir = TempDir::new().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+
+        let base = commit(&repository, &[], &[("a.txt", lines(&[])), ("b.txt", lines(&[])), ("c.txt", lines(&[]))]);
+        let ours = commit(&repository, &[base], &[
+            ("a.txt", lines(&[(2, "ours")])),
+            ("b.txt", lines(&[(5, "ours")])),
+            ("c.txt", lines(&[])),
+        ]);
+        let theirs = commit(&repository, &[base], &[
+            ("a.txt", lines(&[(2, "theirs"), (18, "theirs")])),
+            ("b.txt", lines(&[])),
+            ("c.txt", lines(&[(5, "theirs")])),
+        ]);
+        let merge = commit(&repository, &[ours, theirs], &[
+            ("a.txt", lines(&[(2, "resolved"), (18, "theirs")])),
+            ("b.txt", lines(&[(5, "ours")])),
+            ("c.txt", lines(&[(5, "theirs")])),
+        ]);
+
+        (dir, open(repository, Vec::new()), merge)
+    }
+
+    fn open(repository: Repository, pathspecs: Vec<String>) -> Repo {
+        Repo {
+            repository,
+            diff_settings: DiffSettings::default(),
+            pathspecs,
+            fallback_encoding: encoding_rs::UTF_8,
+        }
+    }
+
+    fn inserted(repo: &Repo, merge: Oid, merge_diff: MergeDiff) -> Vec<(String, Vec<String>)> {
+        let file_tree = repo.commit_diff(&merge.to_string(), CommitKind::Commit, merge_diff).unwrap();
+
+        file_tree
+            .iter_files()
+            .map(|file| {
+                let insertions = file.changes
+                    .iter()
+                    .filter(|change| matches!(change.kind, ChangeKind::Insertion))
+                    .map(|change| change.text.trim_end().to_string())
+                    .collect();
+
+                (file.path.to_string(), insertions)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn first_parent_shows_everything_brought_in() {
+        let (_dir, repo, merge) = conflicted_merge();
+
+        assert_eq!(inserted(&repo, merge, MergeDiff::FirstParent), [
+            (String::from("a.txt"), vec![String::from("resolved"), String::from("theirs")]),
+            (String::from("c.txt"), vec![String::from("theirs")]),
+        ]);
+    }
+
+    #[test]
+    fn all_parents_keeps_files_that_differ_from_each_parent() {
+        let (_dir, repo, merge) = conflicted_merge();
+
+        assert_eq!(inserted(&repo, merge, MergeDiff::AllParents), [
+            (String::from("a.txt"), vec![String::from("resolved"), String::from("theirs")]),
+        ]);
+    }
+
+    #[test]
+    fn overlapping_hunks_leaves_the_conflict_resolution() {
+        let (_dir, repo, merge) = conflicted_merge();
+
+        assert_eq!(inserted(&repo, merge, MergeDiff::OverlappingHunks), [
+            (String::from("a.txt"), vec![String::from("resolved")]),
+        ]);
+    }
+
+    #[test]
+    fn parent_diffs_against_the_given_parent() {
+        let (_dir, repo, merge) = conflicted_merge();
+
+        assert_eq!(inserted(&repo, merge, MergeDiff::Parent(1)), [
+            (String::from("a.txt"), vec![String::from("resolved")]),
+            (String::from("b.txt"), vec![String::from("ours")]),
+        ]);
+    }
+
+    #[test]
+    fn commit_filter_only_searches_the_given_paths() {
+        let dir = TempDir::new().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+
+        let base = commit(&repository, &[], &[("src.txt", lines(&[])), ("vendor.txt", lines(&[]))]);
+        let hash = commit(&repository, &[base], &[("src.txt", lines(&[])), ("vendor.txt", lines(&[(5, "needle")]))]).to_string();
+
+        let all = open(repository, Vec::new());
+        assert!(all.commit_matches(&hash, &[String::from("vendor")], &[]).unwrap());
+        assert!(all.commit_matches(&hash, &[], &[String::from("needle")]).unwrap());
+
+        let without_vendor = open(all.repository, vec![String::from("!vendor.txt"), String::from("*")]);
+        assert!(!without_vendor.commit_matches(&hash, &[String::from("vendor")], &[]).unwrap());
+        assert!(!without_vendor.commit_matches(&hash, &[], &[String::from("needle")]).unwrap());
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::file_tree::{BlobIds, FileTree};
+use crate::state::{Change, ChangeKind};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Old,
+    New,
+}
+
+// a line of a file on one side of a diff
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineAnchor {
+    pub side: Side,
+    pub line: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub commit: String,
+    pub path: String,
+    // the line the comment is shown under, and where the range starts if it spans several lines
+    pub end: LineAnchor,
+    pub start: Option<LineAnchor>,
+    pub body: String,
+    // the file's blobs it was written on, or None for comments saved before these were kept
+    pub blob_ids: Option<BlobIds>,
+}
+
+/*
+ * The reviewer's own comments on the diffs, kept in `.git/branch-diff/review` so they last
+ * between sessions. Comments are attached to a line number in a commit, so they find their
+ * line again however the diff is laid out, as long as the commit is the same. The staged,
+ * unstaged and whole branch entries can change under the same name, so comments also keep
+ * the file's blobs and are hidden once the file no longer has them.
+ * */
+#[derive(Debug)]
+pub struct Review {
+    path: PathBuf,
+    pub comments: Vec<Comment>,
+    // a note on each commit as a whole, by commit hash
+    notes: BTreeMap<String, String>,
+}
+
+impl Side {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Side::Old => "old",
+            Side::New => "new",
+        }
+    }
+
+    fn parse(side: &str) -> Option<Self> {
+        match side {
+            "old" => Some(Side::Old),
+            "new" => Some(Side::New),
+            _ => None,
+        }
+    }
+}
+
+impl LineAnchor {
+    // deleted lines are anchored on the old side, and everything else on the new side
+    pub fn of(change: &Change) -> Option<Self> {
+        match change.kind {
+            ChangeKind::Deletion => Some(LineAnchor { side: Side::Old, line: change.old_lineno? }),
+            ChangeKind::Insertion | ChangeKind::Context => Some(LineAnchor { side: Side::New, line: change.new_lineno? }),
+            ChangeKind::HunkHeader | ChangeKind::Comment => None,
+        }
+    }
+
+    pub fn is_on(&self, change: &Change) -> bool {
+        match self.side {
+            Side::Old => change.old_lineno == Some(self.line),
+            Side::New => change.new_lineno == Some(self.line),
+        }
+    }
+}
+
+impl Comment {
+    // the text of the row the comment is shown on
+    pub fn row_text(&self) -> String {
+        format!("{}: {}", self.lines(), self.body)
+    }
+
+    // whether the comment was written on these versions of its file
+    pub fn is_on_version(&self, blob_ids: &BlobIds) -> bool {
+        self.blob_ids.as_ref().is_none_or(|ids| ids == blob_ids)
+    }
+
+    // the lines the comment is on, such as `new lines 3–5`
+    pub fn lines(&self) -> String {
+        match self.start {
+            Some(start) if start.side == self.end.side => {
+                format!("{} lines {}–{}", self.end.side.label(), start.line, self.end.line)
+            },
+            Some(start) => format!(
+                "{} line {} – {} line {}",
+                start.side.label(),
+                start.line,
+                self.end.side.label(),
+                self.end.line,
+            ),
+            None => format!("{} line {}", self.end.side.label(), self.end.line),
+        }
+    }
+}
+
+impl Review {
+    pub fn new(git_dir: &Path) -> Self {
+        Review {
+            path: git_dir.join("branch-diff").join("review"),
+            comments: Vec::new(),
+            notes: BTreeMap::new(),
+        }
+    }
+
+    /*
+     * Reads the review saved in the repository's git directory, one entry per line with
+     * tab separated fields, and `-` for a range start or blob that isn't there:
+     *
+     *   comment  <commit>  <path>  <side>  <line>  <start side>  <start line>  <body>  <old blob>  <new blob>
+     *   note     <commit>  <body>
+     *
+     * Bodies have backslashes, tabs and newlines escaped. Comments without the blob fields
+     * were saved by an earlier version and are shown whatever the file's blobs are.
+     * */
+    pub fn load(git_dir: &Path) -> io::Result<Self> {
+        let mut review = Review::new(git_dir);
+
+        let contents = match fs::read_to_string(&review.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(review),
+            Err(e) => return Err(e),
+        };
+
+        for line in contents.lines() {
+            if let ["note", commit, body] = line.split('\t').collect::<Vec<&str>>()[..] {
+                review.notes.insert(commit.to_string(), unescape(body));
+                continue;
+            }
+
+            match parse_comment(line) {
+                Some(comment) => review.comments.push(comment),
+                None => tracing::error!("skipping malformed line in {}: {line}", review.path.display()),
+            }
+        }
+
+        Ok(review)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let comments = self.comments.iter().map(|comment| {
+            let (start_side, start_line) = match comment.start {
+                Some(start) => (start.side.label(), start.line.to_string()),
+                None => ("-", String::from("-")),
+            };
+
+            let mut line = format!(
+                "comment\t{}\t{}\t{}\t{}\t{start_side}\t{start_line}\t{}",
+                comment.commit,
+                comment.path,
+                comment.end.side.label(),
+                comment.end.line,
+                escape(comment.body.as_str()),
+            );
+
+            if let Some(blob_ids) = &comment.blob_ids {
+                line.push_str(&format!(
+                    "\t{}\t{}",
+                    blob_ids.old.as_deref().unwrap_or("-"),
+                    blob_ids.new.as_deref().unwrap_or("-"),
+                ));
+            }
+
+            line + "\n"
+        });
+
+        let notes = self.notes
+            .iter()
+            .map(|(commit, body)| format!("note\t{commit}\t{}\n", escape(body.as_str())));
+
+        fs::write(&self.path, comments.chain(notes).collect::<String>())
+    }
+
+    pub fn add_comment(&mut self, comment: Comment) -> io::Result<()> {
+        self.comments.push(comment);
+        self.save()
+    }
+
+    pub fn edit_comment(&mut self, idx: usize, body: String) -> io::Result<()> {
+        if let Some(comment) = self.comments.get_mut(idx) {
+            comment.body = body;
+        }
+
+        self.save()
+    }
+
+    pub fn delete_comment(&mut self, idx: usize) -> io::Result<()> {
+        if idx < self.comments.len() {
+            self.comments.remove(idx);
+        }
+
+        self.save()
+    }
+
+    pub fn note(&self, commit: &str) -> Option<&str> {
+        self.notes.get(commit).map(String::as_str)
+    }
+
+    // an empty note removes the one there was
+    pub fn set_note(&mut self, commit: &str, body: String) -> io::Result<()> {
+        if body.trim().is_empty() {
+            self.notes.remove(commit);
+        } else {
+            self.notes.insert(commit.to_string(), body);
+        }
+
+        self.save()
+    }
+
+    // indices of the comments shown under the given line of a commit's file, in order
+    pub fn comments_under<'a>(&'a self, commit: &'a str, path: &'a str, blob_ids: &'a BlobIds, change: &'a Change) -> impl Iterator<Item = usize> + 'a {
+        self.comments
+            .iter()
+            .enumerate()
+            .filter(move |(_, comment)| {
+                comment.commit == commit
+                    && comment.path == path
+                    && comment.is_on_version(blob_ids)
+                    && comment.end.is_on(change)
+            })
+            .map(|(idx, _)| idx)
+    }
+
+    // shows the commit's comments in its diff, under the lines they're attached to
+    pub fn annotate(&self, file_tree: &mut FileTree, commit: &str) {
+        file_tree.set_comments(|path, blob_ids, change| {
+            self.comments_under(commit, path, blob_ids, change)
+                .map(|idx| self.comments[idx].row_text())
+                .collect()
+        });
+    }
+}
+
+fn parse_comment(line: &str) -> Option<Comment> {
+    let fields = line.split('\t').collect::<Vec<&str>>();
+
+    let (&["comment", commit, path, side, line, start_side, start_line, body], blobs) = fields.split_at_checked(8)? else {
+        return None;
+    };
+
+    let blob_id = |id: &str| (id != "-").then(|| id.to_string());
+
+    let blob_ids = match blobs {
+        [] => None,
+        [old, new] => Some(BlobIds { old: blob_id(old), new: blob_id(new) }),
+        _ => return None,
+    };
+
+    let start = match (start_side, start_line) {
+        ("-", "-") => None,
+        (start_side, start_line) => Some(LineAnchor { side: Side::parse(start_side)?, line: start_line.parse().ok()? }),
+    };
+
+    Some(Comment {
+        commit: commit.to_string(),
+        path: path.to_string(),
+        end: LineAnchor { side: Side::parse(side)?, line: line.parse().ok()? },
+        start,
+        body: unescape(body),
+        blob_ids,
+    })
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+pub fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('t') => unescaped.push('\t'),
+                Some('n') => unescaped.push('\n'),
+                Some(other) => unescaped.push(other),
+                None => unescaped.push('\\'),
+            },
+            c => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn comment(body: &str, blob_ids: Option<BlobIds>) -> Comment {
+        Comment {
+            commit: String::from("abc123"),
+            path: String::from("src/main.rs"),
+            end: LineAnchor { side: Side::New, line: 3 },
+            start: Some(LineAnchor { side: Side::Old, line: 2 }),
+            body: body.to_string(),
+            blob_ids,
+        }
+    }
+
+    fn blob_ids(old: &str, new: &str) -> BlobIds {
+        BlobIds { old: Some(old.to_string()), new: Some(new.to_string()) }
+    }
+
+    fn inserted_line(line: u32) -> Change {
+        Change {
+            text: String::from("line\n"),
+            kind: ChangeKind::Insertion,
+            encoding: None,
+            old_lineno: None,
+            new_lineno: Some(line),
+        }
+    }
+
+    #[test]
+    fn escaping_round_trips() {
+        let body = "tab\there\nnew line, a \\ backslash and a literal \\n\\";
+
+        assert!(!escape(body).contains(['\t', '\n']));
+        assert_eq!(unescape(&escape(body)), body);
+    }
+
+    #[test]
+    fn comments_and_notes_survive_a_reload() {
+        let dir = TempDir::new().unwrap();
+        let body = "first\tcolumn\nsecond line with C:\\path";
+
+        let mut review = Review::new(dir.path());
+        review.add_comment(comment(body, Some(BlobIds { old: None, new: Some(String::from("def456")) }))).unwrap();
+        review.set_note("abc123", String::from("looks\tgood\nto me")).unwrap();
+
+        let loaded = Review::load(dir.path()).unwrap();
+
+        assert_eq!(loaded.comments.len(), 1);
+        assert_eq!(loaded.comments[0].body, body);
+        assert_eq!(loaded.comments[0].start, Some(LineAnchor { side: Side::Old, line: 2 }));
+        assert_eq!(loaded.comments[0].blob_ids, Some(BlobIds { old: None, new: Some(String::from("def456")) }));
+        assert_eq!(loaded.note("abc123"), Some("looks\tgood\nto me"));
+    }
+
+    #[test]
+    fn malformed_lines_are_skipped() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("branch-diff");
+        fs::create_dir_all(&path).unwrap();
+
+        fs::write(path.join("review"), [
+            "comment\tabc123\tsrc/main.rs\tnew\t3\t-\t-\tkept",
+            "comment\tabc123\tsrc/main.rs\tsideways\t3\t-\t-\tbad side",
+            "comment\tabc123\tsrc/main.rs\tnew\tthree\t-\t-\tbad line number",
+            "comment\tabc123\tsrc/main.rs\tnew\t3\t-\t-\tone blob\tdef456",
+            "comment\tabc123\tsrc/main.rs\tnew",
+            "something else entirely",
+            "",
+        ].join("\n")).unwrap();
+
+        let review = Review::load(dir.path()).unwrap();
+
+        assert_eq!(review.comments.len(), 1);
+        assert_eq!(review.comments[0].body, "kept");
+        assert_eq!(review.comments[0].blob_ids, None);
+    }
+
+    #[test]
+    fn comments_on_other_blobs_are_hidden() {
+        let dir = TempDir::new().unwrap();
+        let mut review = Review::new(dir.path());
+
+        review.comments = vec![
+            comment("written on these blobs", Some(blob_ids("aaa", "bbb"))),
+            comment("written before the file changed", Some(blob_ids("aaa", "ccc"))),
+            comment("saved without blobs", None),
+        ];
+
+        let shown: Vec<usize> = review.comments_under("abc123", "src/main.rs", &blob_ids("aaa", "bbb"), &inserted_line(3)).collect();
+        assert_eq!(shown, [0, 2]);
+
+        let other_line: Vec<usize> = review.comments_under("abc123", "src/main.rs", &blob_ids("aaa", "bbb"), &inserted_line(4)).collect();
+        assert!(other_line.is_empty());
+    }
+}
@@ -0,0 +1,93 @@
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+
+use crate::file_tree::FileTree;
+use crate::state::{Change, ChangeKind};
+
+/*
+ * A regex search over the lines of the selected commit's diff. Patterns without any
+ * uppercase letters match case-insensitively, as with smartcase in vim or less.
+ * */
+#[derive(Debug, Clone)]
+pub struct Search {
+    pub regex: Regex,
+    pub scope: SearchScope,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchScope {
+    All,
+    Insertions,
+    Deletions,
+}
+
+impl Search {
+    pub fn new(pattern: &str, scope: SearchScope) -> Result<Self, regex::Error> {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(!pattern.chars().any(char::is_uppercase))
+            .build()?;
+
+        Ok(Search { regex, scope })
+    }
+
+    pub fn pattern(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    // byte ranges of each match within the change's text, empty if the change is out of scope
+    pub fn find_in(&self, change: &Change) -> Vec<Range<usize>> {
+        let in_scope = match self.scope {
+            SearchScope::All => !matches!(change.kind, ChangeKind::HunkHeader | ChangeKind::Comment),
+            SearchScope::Insertions => matches!(change.kind, ChangeKind::Insertion),
+            SearchScope::Deletions => matches!(change.kind, ChangeKind::Deletion),
+        };
+
+        if !in_scope {
+            return Vec::new();
+        }
+
+        self.regex
+            .find_iter(change.text.trim_end_matches('\n'))
+            .filter(|found| !found.is_empty())
+            .map(|found| found.range())
+            .collect()
+    }
+
+    // diff rows, as counted by the files' `scroll_start`, that contain at least one match
+    pub fn matching_lines<'a>(&'a self, file_tree: &'a FileTree) -> impl Iterator<Item = usize> + 'a {
+        file_tree
+            .iter_files()
+            .flat_map(move |file| {
+                let scroll_start = file.scroll_start;
+
+                (0..file.line_count())
+                    .filter(move |&row| {
+                        let (old, new) = file.row(row);
+
+                        [old, new]
+                            .into_iter()
+                            .flatten()
+                            .any(|idx| !self.find_in(&file.changes[idx]).is_empty())
+                    })
+                    .map(move |row| scroll_start + row)
+            })
+    }
+}
+
+impl SearchScope {
+    pub fn next(self) -> Self {
+        match self {
+            SearchScope::All => SearchScope::Insertions,
+            SearchScope::Insertions => SearchScope::Deletions,
+            SearchScope::Deletions => SearchScope::All,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SearchScope::All => "all lines",
+            SearchScope::Insertions => "insertions",
+            SearchScope::Deletions => "deletions",
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use git2::Config;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{self, PathBuf};
+use std::process::{Command, Output, Stdio};
+use tempfile::NamedTempFile;
+
+const PGP_HEADER: &str = "-----BEGIN PGP SIGNATURE-----";
+const SSH_HEADER: &str = "-----BEGIN SSH SIGNATURE-----";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    Good { signer: String },
+    Bad,
+    // signed, but by a key that isn't in the keyring or allowed signers file
+    UnknownKey,
+    // signed, but the signature couldn't be checked, with the reason why
+    Unverifiable(String),
+    Unsigned,
+}
+
+/*
+ * Verifies commit signatures by handing them to the local `gpg` or `ssh-keygen`, the same
+ * way git does. Programs and key locations come from git's own `gpg.*` config, and can be
+ * overridden by command line flags.
+ * */
+#[derive(Debug, Clone)]
+pub struct SignatureVerifier {
+    pub gpg_program: String,
+    // checked instead of the default keyring when set
+    pub gpg_keyring: Option<PathBuf>,
+    pub ssh_program: String,
+    // without one no SSH signature can be trusted, so they all show as unknown keys
+    pub allowed_signers: Option<PathBuf>,
+}
+
+impl SignatureStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SignatureStatus::Good { .. } => "✔ signed",
+            SignatureStatus::Bad => "✘ bad signature",
+            SignatureStatus::UnknownKey => "? unknown key",
+            SignatureStatus::Unverifiable(_) => "! unverifiable",
+            SignatureStatus::Unsigned => "unsigned",
+        }
+    }
+}
+
+impl SignatureVerifier {
+    pub fn from_config(config: &Config) -> Self {
+        let get_string = |keys: &[&str]| keys.iter().find_map(|key| config.get_string(key).ok());
+
+        SignatureVerifier {
+            gpg_program: get_string(&["gpg.openpgp.program", "gpg.program"]).unwrap_or_else(|| String::from("gpg")),
+            gpg_keyring: get_string(&["branch-diff.gpgKeyring"]).map(PathBuf::from),
+            ssh_program: get_string(&["gpg.ssh.program"]).unwrap_or_else(|| String::from("ssh-keygen")),
+            allowed_signers: config.get_path("gpg.ssh.allowedSignersFile").ok(),
+        }
+    }
+
+    pub fn verify(&self, signature: &[u8], signed_data: &[u8]) -> io::Result<SignatureStatus> {
+        let mut signature_file = NamedTempFile::new()?;
+        signature_file.write_all(signature)?;
+
+        let mut data_file = NamedTempFile::new()?;
+        data_file.write_all(signed_data)?;
+
+        let signature_text = String::from_utf8_lossy(signature);
+
+        if signature_text.starts_with(SSH_HEADER) {
+            self.verify_ssh(&signature_file, &data_file)
+        } else if signature_text.starts_with(PGP_HEADER) {
+            self.verify_gpg(&signature_file, &data_file)
+        } else {
+            // e.g. x509 signatures, which there's no local tool configured to check
+            Ok(SignatureStatus::Unverifiable(String::from("no program to check this kind of signature")))
+        }
+    }
+
+    fn verify_gpg(&self, signature_file: &NamedTempFile, data_file: &NamedTempFile) -> io::Result<SignatureStatus> {
+        let mut command = Command::new(&self.gpg_program);
+
+        if let Some(keyring) = &self.gpg_keyring {
+            // gpg looks for relative keyrings in its home directory rather than the working directory
+            command.arg("--no-default-keyring").arg("--keyring").arg(path::absolute(keyring)?);
+        }
+
+        let output = command
+            .args(["--batch", "--status-fd=1", "--verify"])
+            .arg(signature_file.path())
+            .arg(data_file.path())
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()?;
+
+        Ok(parse_gpg_status(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    fn verify_ssh(&self, signature_file: &NamedTempFile, data_file: &NamedTempFile) -> io::Result<SignatureStatus> {
+        let run = |args: &[&str], extra: &[&OsStr]| -> io::Result<Output> {
+            Command::new(&self.ssh_program)
+                .args(args)
+                .args(extra)
+                .stdin(Stdio::from(File::open(data_file.path())?))
+                .stderr(Stdio::null())
+                .output()
+        };
+
+        let Some(allowed_signers) = &self.allowed_signers else {
+            return Self::check_ssh_unknown(&run, signature_file);
+        };
+
+        let principals = run(
+            &["-Y", "find-principals"],
+            &["-f".as_ref(), allowed_signers.as_os_str(), "-s".as_ref(), signature_file.path().as_os_str()],
+        )?;
+
+        let Some(principal) = parse_principal(&principals.stdout, principals.status.success()) else {
+            return Self::check_ssh_unknown(&run, signature_file);
+        };
+
+        let verified = run(
+            &["-Y", "verify", "-n", "git"],
+            &[
+                "-f".as_ref(), allowed_signers.as_os_str(),
+                "-I".as_ref(), principal.as_ref(),
+                "-s".as_ref(), signature_file.path().as_os_str(),
+            ],
+        )?;
+
+        if verified.status.success() {
+            Ok(SignatureStatus::Good { signer: principal })
+        } else {
+            Ok(SignatureStatus::Bad)
+        }
+    }
+
+    // for signers that aren't allowed, still tell a signature that doesn't match the commit apart
+    fn check_ssh_unknown(
+        run: &impl Fn(&[&str], &[&OsStr]) -> io::Result<Output>,
+        signature_file: &NamedTempFile,
+    ) -> io::Result<SignatureStatus> {
+        let checked = run(
+            &["-Y", "check-novalidate", "-n", "git"],
+            &["-s".as_ref(), signature_file.path().as_os_str()],
+        )?;
+
+        if checked.status.success() {
+            Ok(SignatureStatus::UnknownKey)
+        } else {
+            Ok(SignatureStatus::Bad)
+        }
+    }
+}
+
+/*
+ * Reads the machine-readable lines gpg writes with `--status-fd`. A bad, expired or revoked
+ * signature wins over anything else, and a missing public key is an unknown key. Anything
+ * else that stops gpg from checking the signature, like an unsupported algorithm, leaves it
+ * unverifiable.
+ * */
+fn parse_gpg_status(status: &str) -> SignatureStatus {
+    let mut result = SignatureStatus::Unverifiable(String::from("gpg gave no verdict"));
+
+    for line in status.lines() {
+        let Some(line) = line.strip_prefix("[GNUPG:] ") else {
+            continue;
+        };
+
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+
+        match keyword {
+            "BADSIG" | "EXPKEYSIG" | "REVKEYSIG" => return SignatureStatus::Bad,
+            "GOODSIG" => {
+                // the rest is the key id followed by the signer's user id
+                let signer = rest.split_once(' ').map_or(rest, |(_, user_id)| user_id);
+                result = SignatureStatus::Good { signer: signer.to_string() };
+            },
+            "NO_PUBKEY" => result = SignatureStatus::UnknownKey,
+            // `<key id> <algo> <hash algo> <class> <time> <rc>`, where rc 9 is a missing key
+            "ERRSIG" => {
+                result = match rest.split(' ').nth(5) {
+                    Some("9") => SignatureStatus::UnknownKey,
+                    Some("4") => SignatureStatus::Unverifiable(String::from("gpg doesn't support the signature's algorithm")),
+                    _ => SignatureStatus::Unverifiable(String::from("gpg couldn't check the signature")),
+                };
+            },
+            _ => {},
+        }
+    }
+
+    result
+}
+
+// `ssh-keygen -Y find-principals` prints the matching principals one per line
+fn parse_principal(stdout: &[u8], success: bool) -> Option<String> {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .next()
+        .map(|line| line.trim().to_string())
+        .filter(|line| success && !line.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_ID: &str = "0123456789ABCDEF";
+
+    #[test]
+    fn good_signature_names_the_signer() {
+        let status = format!(
+            "[GNUPG:] NEWSIG\n[GNUPG:] GOODSIG {KEY_ID} Jane Doe <jane@example.com>\n[GNUPG:] VALIDSIG {KEY_ID}\n",
+        );
+
+        assert_eq!(parse_gpg_status(&status), SignatureStatus::Good { signer: String::from("Jane Doe <jane@example.com>") });
+    }
+
+    #[test]
+    fn bad_and_expired_signatures_are_bad() {
+        let bad = format!("[GNUPG:] BADSIG {KEY_ID} Jane Doe <jane@example.com>\n");
+        let expired = format!("[GNUPG:] EXPKEYSIG {KEY_ID} Jane Doe <jane@example.com>\n[GNUPG:] GOODSIG {KEY_ID} Jane\n");
+
+        assert_eq!(parse_gpg_status(&bad), SignatureStatus::Bad);
+        assert_eq!(parse_gpg_status(&expired), SignatureStatus::Bad);
+    }
+
+    #[test]
+    fn missing_public_key_is_unknown() {
+        let status = format!("[GNUPG:] ERRSIG {KEY_ID} 1 8 00 1700000000 9 -\n[GNUPG:] NO_PUBKEY {KEY_ID}\n");
+
+        assert_eq!(parse_gpg_status(&status), SignatureStatus::UnknownKey);
+        assert_eq!(parse_gpg_status(&format!("[GNUPG:] NO_PUBKEY {KEY_ID}\n")), SignatureStatus::UnknownKey);
+    }
+
+    #[test]
+    fn other_errors_are_unverifiable() {
+        let unsupported = format!("[GNUPG:] ERRSIG {KEY_ID} 99 8 00 1700000000 4 -\n");
+
+        assert!(matches!(parse_gpg_status(&unsupported), SignatureStatus::Unverifiable(_)));
+        assert!(matches!(parse_gpg_status("gpg: no valid OpenPGP data found.\n"), SignatureStatus::Unverifiable(_)));
+        assert!(matches!(parse_gpg_status(""), SignatureStatus::Unverifiable(_)));
+    }
+
+    #[test]
+    fn principal_is_the_first_line() {
+        assert_eq!(parse_principal(b"jane@example.com \nother@example.com\n", true), Some(String::from("jane@example.com")));
+    }
+
+    #[test]
+    fn no_principal_when_none_is_found() {
+        assert_eq!(parse_principal(b"", true), None);
+        assert_eq!(parse_principal(b"\n", true), None);
+        assert_eq!(parse_principal(b"jane@example.com\n", false), None);
+    }
+}
@@ -1,38 +1,188 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::io;
 
-use crate::file_tree::FileTree;
+use crate::commit_filter::CommitFilter;
+use crate::diff_settings::DiffSettings;
+use crate::context::{Expansion, SourceLine};
+use crate::file_tree::{DiffView, FileTree, FileTreeFilesItem};
+use crate::review::{Comment, LineAnchor, Review};
+use crate::search::{Search, SearchScope};
+use crate::signature::SignatureStatus;
+use crate::verdict::{Verdict, VerdictKind};
+use crate::viewed::ViewedMarks;
 
 #[derive(Debug)]
 pub struct AppState {
     pub exit: bool,
-    pub from_branch: String,
-    pub into_branch: String,
+    pub from_branch: Option<String>,
+    pub into_branch: Option<String>,
 
     pub commits: HashMap<String, Commit>,
+    // the commits currently listed, which is all of them unless a filter narrows it down
     pub commits_order: Vec<String>,
+    pub unfiltered_order: Vec<String>,
+    pub commit_filter: Option<CommitFilter>,
+    pub filter_status: FilterStatus,
+    pub diff_settings: DiffSettings,
+    pub diff_view: DiffView,
+    pub viewed: ViewedMarks,
+    pub review: Review,
 
     pub selected_pane: Pane,
+    pub show_commit_details: bool,
+    // text input shown in the bottom bar, which takes all key presses while open
+    pub prompt: Option<Prompt>,
+    // the outcome of the last action that has one to report, shown until the next key press
+    pub notice: Option<String>,
+    pub search: Option<Search>,
     pub selected_commit: usize,
     pub selected_file: usize,
 
-    pub scroll_position: i16,
-    pub scroll_height: i16,
-    pub lines_rendered: i16,
+    pub scroll_position: i32,
+    // the diff line actions such as expanding context apply to, always kept in the viewport
+    pub cursor: usize,
+    // the other end of the lines selected for a comment, if a range has been started
+    pub range_start: Option<usize>,
+    pub scroll_height: i32,
+    pub lines_rendered: i32,
+
+    pub pending_diffs: usize,
+    // advanced once per event loop iteration, used to animate the loading spinner
+    pub tick: usize,
 }
 
 #[derive(Debug)]
 pub struct Commit {
     pub hash: String,
+    pub short_hash: String,
     pub message: Option<String>,
-    pub author: String,
-    pub file_tree: FileTree,
+    // None for the pseudo-commits, which have no author
+    pub author: Option<Identity>,
+    // only set when it differs from the author
+    pub committer: Option<Identity>,
+    pub parents: Vec<String>,
+    pub trailers: Vec<(String, String)>,
+    pub kind: CommitKind,
+    pub merge_diff: MergeDiff,
+    // None until verified, and always for the pseudo-commits
+    pub signature: Option<SignatureStatus>,
+    // every reviewer's verdict, empty for the pseudo-commits
+    pub verdicts: Vec<Verdict>,
+    // settings the file tree was computed with, so it can be rebuilt once they change
+    pub diff_settings: DiffSettings,
+    // None until the diff is first computed
+    pub file_tree: Option<FileTree>,
     pub diff_len: usize,
+    // set when none of its changes are in the paths given, which is found out ahead of its diff
+    pub outside_paths: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Identity {
+    pub name: String,
+    pub email: String,
+    // seconds since the epoch, and the offset of the timezone it was recorded in
+    pub time: i64,
+    pub offset_minutes: i32,
+}
+
+impl Commit {
+    pub fn is_merge(&self) -> bool {
+        self.parents.len() > 1
+    }
+
+    pub fn needs_diff(&self, diff_settings: &DiffSettings) -> bool {
+        self.file_tree.is_none() || self.diff_settings != *diff_settings
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.file_tree.as_ref().map_or(0, |file_tree| file_tree.iter_files().count())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CommitKind {
+    Commit = 0,
+    WholeBranch = 1,
+    Staged = 2,
+    Unstaged = 3,
+}
+
+/*
+ * How a merge commit's diff is computed. All but `Parent` are diffs against the first parent:
+ * `AllParents` keeps only the files that differ from every parent, and `OverlappingHunks`
+ * further keeps only the hunks whose lines were changed relative to every parent too.
+ * `Parent` diffs against the parent with the given index.
+ * */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MergeDiff {
+    FirstParent,
+    AllParents,
+    OverlappingHunks,
+    Parent(usize),
+}
+
+impl MergeDiff {
+    pub fn next(self, parent_count: usize) -> Self {
+        match self {
+            MergeDiff::FirstParent => MergeDiff::AllParents,
+            MergeDiff::AllParents => MergeDiff::OverlappingHunks,
+            MergeDiff::OverlappingHunks if parent_count > 1 => MergeDiff::Parent(1),
+            MergeDiff::Parent(idx) if idx + 1 < parent_count => MergeDiff::Parent(idx + 1),
+            _ => MergeDiff::FirstParent,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            MergeDiff::FirstParent => String::from("first parent"),
+            MergeDiff::AllParents => String::from("vs all parents"),
+            MergeDiff::OverlappingHunks => String::from("overlapping hunks"),
+            MergeDiff::Parent(idx) => format!("parent {}", idx + 1),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub input: String,
+    // set when the input can't be used as it stands, e.g. an invalid regex
+    pub invalid: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum PromptKind {
+    // `origin` is the line to put the cursor back on if the search is cancelled
+    Search { scope: SearchScope, origin: i32 },
+    Filter,
+    // writes a new comment at the cursor, or edits the comment with the given index
+    Comment { editing: Option<usize> },
+    // the note on the selected commit
+    Note,
+    // where to write the review document
+    Export,
+    // the reviewer's verdict on the selected commit, with the prompt's input as its note
+    Verdict { kind: Option<VerdictKind> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterStatus {
+    Matched(usize),
+    // path and `-S` terms are checked in the background, `searched` of `total` commits so far
+    Searching { searched: usize, total: usize },
+    NoMatches,
 }
 
 #[derive(Debug)]
 pub struct Change {
     pub text: String,
     pub kind: ChangeKind,
+    // name of the encoding used when the line wasn't valid UTF-8
+    pub encoding: Option<&'static str>,
+    // line numbers in the old and new file, each missing on the side the line isn't part of
+    pub old_lineno: Option<u32>,
+    pub new_lineno: Option<u32>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -40,6 +190,10 @@ pub enum ChangeKind {
     Context = 0,
     Insertion = 1,
     Deletion = 2,
+    // the first row of every hunk, holding its `@@` header
+    HunkHeader = 3,
+    // a review comment, shown under the line it's attached to
+    Comment = 4,
 }
 
 #[derive(Debug, PartialEq)]
@@ -56,23 +210,41 @@ pub enum Direction {
 
 impl AppState {
     pub fn new(
-        from_branch: String,
-        into_branch: String,
+        from_branch: Option<String>,
+        into_branch: Option<String>,
         commits: HashMap<String, Commit>,
         commits_order: Vec<String>,
+        diff_settings: DiffSettings,
+        viewed: ViewedMarks,
+        review: Review,
     ) -> Self {
         AppState {
             from_branch,
             into_branch,
             exit: false,
             commits,
+            unfiltered_order: commits_order.clone(),
             commits_order,
+            commit_filter: None,
+            filter_status: FilterStatus::NoMatches,
+            diff_settings,
+            diff_view: DiffView::Unified,
+            viewed,
+            review,
             selected_pane: Pane::Diff,
+            show_commit_details: false,
+            prompt: None,
+            notice: None,
+            search: None,
             selected_commit: 0,
             selected_file: 0,
             scroll_position: 0,
+            cursor: 0,
+            range_start: None,
             scroll_height: 0,
             lines_rendered: 0,
+            pending_diffs: 0,
+            tick: 0,
         }
     }
 
@@ -81,9 +253,280 @@ impl AppState {
     }
 
     pub fn get_selected_commit(&self) -> &Commit {
+        self.get_commit(self.selected_commit)
+    }
+
+    pub fn get_commit(&self, index: usize) -> &Commit {
         self.commits
-            .get(self.commits_order[self.selected_commit].as_str())
-            .expect(format!("attempted to get out of bounds commit with index: {}", self.selected_commit).as_str())
+            .get(self.commits_order[index].as_str())
+            .unwrap_or_else(|| panic!("attempted to get out of bounds commit with index: {}", index))
+    }
+
+    /*
+     * Replaces a commit's diff. If it's the selected commit, the viewport is kept on the file
+     * it was showing if that file is still part of the new diff.
+     * */
+    pub fn set_commit_diff(&mut self, index: usize, mut file_tree: FileTree, merge_diff: MergeDiff, diff_settings: DiffSettings) {
+        self.review.annotate(&mut file_tree, self.commits_order[index].as_str());
+        file_tree.layout(self.diff_view);
+
+        let is_selected = index == self.selected_commit;
+        let scroll_position = self.scroll_position.max(0) as usize;
+
+        let anchor = self.get_commit(index)
+            .file_tree
+            .as_ref()
+            .filter(|_| is_selected)
+            .and_then(|file_tree| file_tree.file_at(scroll_position))
+            .map(|(_, file)| file.path.to_string());
+
+        let commit = self.commits
+            .get_mut(self.commits_order[index].as_str())
+            .unwrap_or_else(|| panic!("attempted to get out of bounds commit with index: {}", index));
+
+        commit.diff_len = file_tree.diff_len();
+        commit.merge_diff = merge_diff;
+        commit.diff_settings = diff_settings;
+        let file_tree = commit.file_tree.insert(file_tree);
+
+        if !is_selected {
+            return;
+        }
+
+        let anchored = anchor.and_then(|path| {
+            file_tree
+                .iter_files()
+                .enumerate()
+                .find(|(_, file)| file.path == path)
+                .map(|(idx, file)| (idx, file.scroll_start))
+        });
+
+        let (selected_file, scroll_start) = anchored.unwrap_or((0, 0));
+        self.selected_file = selected_file;
+        self.scroll_position = scroll_start as i32;
+        self.cursor = scroll_start;
+        self.range_start = None;
+    }
+
+    // the index and contents of the file the cursor is in
+    pub fn file_at_cursor(&self) -> Option<(usize, FileTreeFilesItem<'_>)> {
+        self.get_selected_commit()
+            .file_tree
+            .as_ref()
+            .and_then(|file_tree| file_tree.file_at(self.cursor))
+    }
+
+    /*
+     * Shows more unchanged lines around a hunk of the selected commit's `file_idx`th file.
+     * The cursor and viewport stay where they are, so lines revealed above a hunk push
+     * the rest of the diff down.
+     * */
+    pub fn expand_context(
+        &mut self,
+        file_idx: usize,
+        change_idx: usize,
+        expansion: Expansion,
+        old_lines: &[SourceLine],
+        new_lines: &[SourceLine],
+    ) {
+        let view = self.diff_view;
+
+        let Some(commit) = self.commits.get_mut(self.commits_order[self.selected_commit].as_str()) else {
+            return;
+        };
+
+        let Some(file_tree) = &mut commit.file_tree else {
+            return;
+        };
+
+        file_tree.expand_context(file_idx, change_idx, expansion, old_lines, new_lines);
+        // comments on the revealed lines can be shown now
+        self.review.annotate(file_tree, commit.hash.as_str());
+        file_tree.layout(view);
+        commit.diff_len = file_tree.diff_len();
+    }
+
+    // starts a range of lines to comment on at the cursor, or drops the one already started
+    pub fn toggle_range(&mut self) {
+        self.range_start = match self.range_start {
+            Some(_) => None,
+            None => Some(self.cursor),
+        };
+    }
+
+    // the rows highlighted as the cursor, which take in the range being selected
+    pub fn cursor_rows(&self) -> (usize, usize) {
+        let start = self.range_start.unwrap_or(self.cursor);
+        (start.min(self.cursor), start.max(self.cursor))
+    }
+
+    /*
+     * Where a new comment at the cursor would go: its file, and the first and last lines of
+     * the selected range. Rows with no line of their own, such as hunk headers, are skipped
+     * over, and a range that crosses into another file can't be commented on.
+     * */
+    pub fn comment_anchor(&self) -> Option<(String, Option<LineAnchor>, LineAnchor)> {
+        let (first, last) = self.cursor_rows();
+
+        let anchors: Vec<(&str, LineAnchor)> = (first..=last)
+            .filter_map(|row| self.line_anchor_at(row))
+            .collect();
+
+        let (start_path, start) = anchors.first()?;
+        let (end_path, end) = anchors.last()?;
+
+        if start_path != end_path {
+            return None;
+        }
+
+        Some((end_path.to_string(), Some(*start).filter(|start| start != end), *end))
+    }
+
+    // on a split row with lines on both sides, the new side's line is the one anchored to
+    fn line_anchor_at(&self, row: usize) -> Option<(&str, LineAnchor)> {
+        let (_, file) = self.get_selected_commit().file_tree.as_ref()?.file_at(row)?;
+        let (old, new) = file.row(row - file.scroll_start);
+
+        Some((file.path, LineAnchor::of(&file.changes[new.or(old)?])?))
+    }
+
+    // the index in the review of the comment whose row the cursor is on
+    pub fn comment_at_cursor(&self) -> Option<usize> {
+        let (_, file) = self.file_at_cursor()?;
+        let (old, _) = file.row(self.cursor - file.scroll_start);
+        let idx = old?;
+
+        if !matches!(file.changes[idx].kind, ChangeKind::Comment) {
+            return None;
+        }
+
+        // comment rows follow the line they're attached to in the order the review lists them
+        let line_idx = file.changes[..idx]
+            .iter()
+            .rposition(|change| !matches!(change.kind, ChangeKind::Comment))?;
+
+        self.review
+            .comments_under(self.get_selected_commit().hash.as_str(), file.path, file.blob_ids, &file.changes[line_idx])
+            .nth(idx - line_idx - 1)
+    }
+
+    pub fn add_comment(&mut self, body: String) -> io::Result<()> {
+        let Some((path, start, end)) = self.comment_anchor() else {
+            return Ok(());
+        };
+
+        let commit = self.get_selected_commit();
+
+        let blob_ids = commit.file_tree
+            .iter()
+            .flat_map(FileTree::iter_files)
+            .find(|file| file.path == path)
+            .map(|file| file.blob_ids.clone());
+
+        let commit = commit.hash.clone();
+
+        self.review.add_comment(Comment { commit, path, end, start, body, blob_ids })?;
+        self.range_start = None;
+        self.refresh_comments();
+
+        Ok(())
+    }
+
+    pub fn edit_comment(&mut self, idx: usize, body: String) -> io::Result<()> {
+        self.review.edit_comment(idx, body)?;
+        self.refresh_comments();
+
+        Ok(())
+    }
+
+    pub fn set_note(&mut self, body: String) -> io::Result<()> {
+        let commit = &self.commits[self.commits_order[self.selected_commit].as_str()];
+        self.review.set_note(commit.hash.as_str(), body)
+    }
+
+    pub fn delete_comment_at_cursor(&mut self) -> io::Result<()> {
+        let Some(idx) = self.comment_at_cursor() else {
+            return Ok(());
+        };
+
+        self.review.delete_comment(idx)?;
+        self.refresh_comments();
+
+        Ok(())
+    }
+
+    // shows the selected commit's comments as they now are, keeping the cursor within the diff
+    fn refresh_comments(&mut self) {
+        let view = self.diff_view;
+
+        let Some(commit) = self.commits.get_mut(self.commits_order[self.selected_commit].as_str()) else {
+            return;
+        };
+
+        let Some(file_tree) = &mut commit.file_tree else {
+            return;
+        };
+
+        self.review.annotate(file_tree, commit.hash.as_str());
+        file_tree.layout(view);
+        commit.diff_len = file_tree.diff_len();
+
+        self.cursor = self.cursor.min(commit.diff_len.saturating_sub(1));
+    }
+
+    /*
+     * Switches between the unified and split views. Every loaded diff is laid out again, and
+     * the change under the cursor is scrolled to the top of the viewport.
+     * */
+    pub fn toggle_diff_view(&mut self) {
+        self.diff_view = self.diff_view.toggle();
+
+        let anchor = self.file_at_cursor().map(|(file_idx, file)| {
+            let (old, new) = file.row(self.cursor - file.scroll_start);
+            (file_idx, old.or(new))
+        });
+
+        for commit in self.commits.values_mut() {
+            if let Some(file_tree) = &mut commit.file_tree {
+                file_tree.layout(self.diff_view);
+                commit.diff_len = file_tree.diff_len();
+            }
+        }
+
+        let Some((file_idx, change_idx)) = anchor else {
+            return;
+        };
+
+        let line = self.get_selected_commit()
+            .file_tree
+            .as_ref()
+            .and_then(|file_tree| file_tree.iter_files().nth(file_idx))
+            .map_or(0, |file| file.scroll_start + change_idx.map_or(0, |idx| file.row_of(idx)));
+
+        self.scroll_to(line as i32);
+    }
+
+    /*
+     * Marks or unmarks as viewed whatever is selected in the focused pane: the selected
+     * commit, the selected file, or the file under the cursor in the diff pane.
+     * */
+    pub fn toggle_viewed(&mut self) -> io::Result<()> {
+        let commit = &self.commits[self.commits_order[self.selected_commit].as_str()];
+
+        let Some(file_tree) = &commit.file_tree else {
+            return Ok(());
+        };
+
+        let file = match self.selected_pane {
+            Pane::Commits => return self.viewed.toggle_commit(commit),
+            Pane::Files => file_tree.iter_files().nth(self.selected_file),
+            Pane::Diff => file_tree.file_at(self.cursor).map(|(_, file)| file),
+        };
+
+        match file {
+            Some(file) => self.viewed.toggle_file(commit, file.path, file.blob_ids),
+            None => Ok(()),
+        }
     }
 
     pub fn navigate(&mut self, direction: Direction) {
@@ -91,36 +534,45 @@ impl AppState {
             Pane::Commits => {
                 match direction {
                     Direction::Down => self.select_commit(
-                        if self.selected_commit == self.commits.len() - 1 { 0 } else { self.selected_commit + 1 }
+                        if self.selected_commit == self.commits_order.len() - 1 { 0 } else { self.selected_commit + 1 }
                     ),
                     Direction::Up => self.select_commit(
-                        if self.selected_commit == 0 { self.commits.len() - 1 } else { self.selected_commit - 1}
+                        if self.selected_commit == 0 { self.commits_order.len() - 1 } else { self.selected_commit - 1}
                     ),
                 }
             },
             Pane::Diff => {
                 match direction {
                     Direction::Down => {
-                        let commit = self.get_selected_commit();
+                        let diff_len = self.get_selected_commit().diff_len;
+
+                        if self.cursor + 1 < diff_len {
+                            self.cursor += 1;
+                        }
 
+                        // scroll once the cursor would move past the bottom of the viewport
                         let scroll_bottom = self.scroll_position + self.lines_rendered;
-                        if scroll_bottom < commit.diff_len as i16 {
+                        if self.cursor as i32 >= scroll_bottom && scroll_bottom < diff_len as i32 {
                             self.scroll_position += 1;
                         }
                     },
                     Direction::Up => {
-                        if self.scroll_position > 0 {
-                            self.scroll_position -= 1;
+                        self.cursor = self.cursor.saturating_sub(1);
+
+                        if (self.cursor as i32) < self.scroll_position {
+                            self.scroll_position = self.cursor as i32;
                         }
                     },
                 }
+
+                self.select_top_file();
             },
             Pane::Files => {
                 match direction {
                     Direction::Down => {
                         let commit = self.get_selected_commit();
 
-                        if self.selected_file < commit.file_tree.iter_files().count() - 1 {
+                        if self.selected_file + 1 < commit.file_count() {
                             self.selected_file += 1;
                         }
                     },
@@ -135,29 +587,138 @@ impl AppState {
     }
 
     pub fn jump_hunk(&mut self, direction: Direction) {
-        match direction {
-            Direction::Down => {
-                let commit = self.get_selected_commit();
-                let next_hunk = commit.file_tree.get_next_hunk(self.scroll_position.try_into().expect("failed to cast scroll_position from i16 to usize"));
+        self.jump(|file_tree, line| match direction {
+            Direction::Down => file_tree.get_next_hunk(line),
+            Direction::Up => file_tree.get_prev_hunk(line),
+        });
+    }
 
-                if let Some(hunk_idx) = next_hunk {
-                    self.scroll_position = hunk_idx as i16;
-                }
-            },
-            Direction::Up => {},
+    pub fn jump_file(&mut self, direction: Direction) {
+        self.jump(|file_tree, line| match direction {
+            Direction::Down => file_tree.get_next_file(line),
+            Direction::Up => file_tree.get_prev_file(line),
+        });
+    }
+
+    // jumps to the first or last line of the diff
+    pub fn jump_to_end(&mut self, direction: Direction) {
+        self.jump(|file_tree, _| match direction {
+            Direction::Down => Some(file_tree.diff_len().saturating_sub(1)),
+            Direction::Up => Some(0),
+        });
+    }
+
+    pub fn jump_match(&mut self, direction: Direction) {
+        let Some(search) = self.search.clone() else {
+            return;
+        };
+
+        self.jump(|file_tree, line| match direction {
+            Direction::Down => search.matching_lines(file_tree).find(|&match_line| match_line > line),
+            Direction::Up => search.matching_lines(file_tree).filter(|&match_line| match_line < line).last(),
+        });
+    }
+
+    pub fn scroll_to(&mut self, line: i32) {
+        self.jump(|_, _| Some(line.max(0) as usize));
+    }
+
+    // used while typing a search, so the first match is found without moving past the start
+    pub fn jump_first_match_from(&mut self, origin: i32) {
+        let Some(search) = self.search.clone() else {
+            return;
+        };
+
+        let origin = origin.max(0) as usize;
+
+        // with nothing to match, go back to where the search started
+        self.jump(|file_tree, _| {
+            search.matching_lines(file_tree)
+                .find(|&match_line| match_line >= origin)
+                .or(Some(origin))
+        });
+    }
+
+    /*
+     * Moves the cursor to the line picked by `target` given the current cursor line and
+     * scrolls it to the top of the viewport, without scrolling past the end. The file now
+     * at the top of the viewport is selected.
+     * */
+    fn jump(&mut self, target: impl FnOnce(&FileTree, usize) -> Option<usize>) {
+        let commit = self.get_selected_commit();
+
+        let Some(file_tree) = &commit.file_tree else {
+            return;
+        };
+
+        let Some(line) = target(file_tree, self.cursor) else {
+            return;
+        };
+
+        let line = line.min(commit.diff_len.saturating_sub(1));
+        let max_scroll = (commit.diff_len as i32 - self.lines_rendered).max(0);
+
+        self.scroll_position = (line as i32).min(max_scroll);
+        self.cursor = line;
+        self.select_top_file();
+    }
+
+    // the files pane follows the file at the top of the viewport
+    fn select_top_file(&mut self) {
+        let top_file = self.get_selected_commit()
+            .file_tree
+            .as_ref()
+            .and_then(|file_tree| file_tree.file_at(self.scroll_position.max(0) as usize))
+            .map(|(file_idx, _)| file_idx);
+
+        if let Some(file_idx) = top_file {
+            self.selected_file = file_idx;
         }
     }
 
     pub fn select_commit(&mut self, index: usize) {
-        if index < self.commits.len() {
+        if index < self.commits_order.len() {
             self.selected_commit = index;
             self.scroll_position = 0;
+            self.cursor = 0;
+            self.range_start = None;
             self.selected_file = 0;
         } else {
             tracing::error!("attempted to select an out of bounds commit index: {index}");
         }
     }
 
+    /*
+     * Lists only the commits that match `filter`, and are in `matched` when given, keeping
+     * the selected commit if it's still listed. If nothing matches, every commit is listed
+     * rather than none. Returns the number of matching commits.
+     * */
+    pub fn filter_commits(&mut self, filter: Option<&CommitFilter>, matched: Option<&HashSet<String>>) -> usize {
+        let selected_hash = self.commits_order[self.selected_commit].clone();
+
+        let filtered: Vec<String> = self.unfiltered_order
+            .iter()
+            .filter(|hash| filter.is_none_or(|filter| filter.matches_metadata(&self.commits[*hash])))
+            .filter(|hash| matched.is_none_or(|matched| matched.contains(*hash)))
+            .cloned()
+            .collect();
+
+        let match_count = filtered.len();
+
+        self.commits_order = if filtered.is_empty() {
+            self.unfiltered_order.clone()
+        } else {
+            filtered
+        };
+
+        match self.commits_order.iter().position(|hash| *hash == selected_hash) {
+            Some(index) => self.selected_commit = index,
+            None => self.select_commit(0),
+        }
+
+        match_count
+    }
+
     pub fn select_pane(&mut self, pane: Pane) {
         self.selected_pane = pane;
     }
@@ -169,21 +730,22 @@ impl AppState {
             },
             Pane::Files => {
                 let commit = self.get_selected_commit();
-                let file = commit.file_tree.iter_files().nth(self.selected_file);
+                let file = commit.file_tree.as_ref().and_then(|file_tree| file_tree.iter_files().nth(self.selected_file));
 
                 let (file_scroll_start, file_diff_len) = match file {
-                    Some(f) => (f.scroll_start as i16, f.changes.len() as i16),
+                    Some(f) => (f.scroll_start as i32, f.line_count() as i32),
                     None => (0, 0),
                 };
 
                 let scroll_bottom = file_scroll_start + self.lines_rendered;
 
-                self.scroll_position = if scroll_bottom < commit.diff_len as i16 {
+                self.scroll_position = if scroll_bottom < commit.diff_len as i32 {
                     file_scroll_start
                 } else {
                     let non_content_lines = self.scroll_height - self.lines_rendered;
                     file_scroll_start + file_diff_len - self.lines_rendered + non_content_lines
                 };
+                self.cursor = file_scroll_start as usize;
 
                 self.select_pane(Pane::Diff);
             },
@@ -1,28 +1,113 @@
 use ratatui::{
     buffer::Buffer,
     layout::Rect,
+    style::Stylize,
     text::{Line, Span},
     widgets::{Paragraph, StatefulWidget, Widget},
 };
 
-use crate::state::AppState;
+use crate::state::{AppState, FilterStatus, PromptKind};
+use crate::viewed::ViewedStatus;
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
 
 #[derive(Debug, Default)]
 pub struct BottomBar {}
 
-impl<'a> StatefulWidget for &BottomBar {
+impl StatefulWidget for &BottomBar {
     type State = AppState;
 
     fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
-        let from_span = Span::from(state.from_branch.as_str());
-        let into_span = Span::from(state.into_branch.as_str());
+        if let Some(prompt) = &state.prompt {
+            let (symbol, hint) = match prompt.kind {
+                PromptKind::Search { scope, .. } => ("/", format!("{} (tab to change)", scope.label())),
+                PromptKind::Filter => ("filter: ", String::from("text author: hash: path: -S")),
+                PromptKind::Comment { .. } => ("comment: ", String::from("enter to save, esc to cancel")),
+                PromptKind::Note => ("note: ", String::from("on the whole commit, empty to remove")),
+                PromptKind::Export => ("export to: ", String::from("markdown, or json for a .json file")),
+                PromptKind::Verdict { kind } => (
+                    "verdict note: ",
+                    format!("{} (tab to change)", kind.map_or("no verdict", |kind| kind.label())),
+                ),
+            };
+
+            let input = Span::from(prompt.input.as_str());
+
+            Paragraph::new(Line::from(vec![
+                Span::from(symbol),
+                if prompt.invalid { input.red() } else { input },
+                Span::from("█").slow_blink(),
+            ])).render(area, buf);
 
-        let bar = Line::from(vec![
-            into_span,
-            " <- ".into(),
-            from_span,
-        ]);
+            Paragraph::new(Line::from(hint.dark_gray()).right_aligned()).render(area, buf);
+            return;
+        }
+
+        let bar = match (&state.from_branch, &state.into_branch) {
+            (Some(from_branch), Some(into_branch)) => Line::from(vec![
+                Span::from(into_branch.as_str()),
+                " <- ".into(),
+                Span::from(from_branch.as_str()),
+            ]),
+            _ => Line::from("working tree"),
+        };
 
         Paragraph::new(bar).render(area, buf);
+
+        let mut status = Vec::new();
+
+        if let Some(notice) = &state.notice {
+            status.push(Span::from(format!("{notice}  ")).cyan());
+        }
+
+        if state.pending_diffs > 0 {
+            let loaded = state.commits.values().filter(|commit| commit.file_tree.is_some()).count();
+            let spinner = SPINNER[state.tick % SPINNER.len()];
+
+            status.push(Span::from(format!("{spinner} loading {loaded}/{} commits  ", state.commits.len())).yellow());
+        }
+
+        if let Some(filter) = &state.commit_filter {
+            let spinner = SPINNER[state.tick % SPINNER.len()];
+
+            status.push(match state.filter_status {
+                FilterStatus::Matched(count) => Span::from(format!(
+                    "filter: {} {count}/{} commits  ",
+                    filter.query(),
+                    state.unfiltered_order.len(),
+                )).cyan(),
+                FilterStatus::Searching { searched, total } => Span::from(format!(
+                    "{spinner} filter: {} searched {searched}/{total}  ",
+                    filter.query(),
+                )).yellow(),
+                FilterStatus::NoMatches => Span::from(format!("filter: {} no commits match  ", filter.query())).red(),
+            });
+        }
+
+        if let Some(search) = &state.search {
+            let count = state.get_selected_commit()
+                .file_tree
+                .as_ref()
+                .map_or(0, |file_tree| search.matching_lines(file_tree).count());
+
+            status.push(Span::from(format!("/{} {count} lines  ", search.pattern())).cyan());
+        }
+
+        let (viewed_files, file_count) = state.viewed.file_progress(state.get_selected_commit());
+
+        let viewed_commits = state.unfiltered_order
+            .iter()
+            .filter(|hash| state.viewed.commit_status(&state.commits[*hash]) == ViewedStatus::Viewed)
+            .count();
+
+        let progress = Span::from(format!(
+            "viewed {viewed_files}/{file_count} files {viewed_commits}/{} commits  ",
+            state.unfiltered_order.len(),
+        ));
+
+        status.push(if viewed_commits == state.unfiltered_order.len() { progress.cyan() } else { progress });
+        status.push(Span::from(state.diff_settings.describe()).dark_gray());
+
+        Paragraph::new(Line::from(status).right_aligned()).render(area, buf);
     }
 }
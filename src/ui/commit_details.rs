@@ -0,0 +1,106 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Flex, Layout, Rect},
+    style::Stylize,
+    symbols::border,
+    text::{Line, Span},
+    widgets::{Block, Clear, Padding, Paragraph, StatefulWidget, Widget, Wrap},
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::signature::SignatureStatus;
+use crate::state::{AppState, Identity};
+use crate::ui::format::{format_time, relative_time};
+
+#[derive(Debug, Default)]
+pub struct CommitDetails {}
+
+impl StatefulWidget for &CommitDetails {
+    type State = AppState;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
+        let [area] = Layout::horizontal([Constraint::Percentage(70)]).flex(Flex::Center).areas(area);
+        let [area] = Layout::vertical([Constraint::Percentage(70)]).flex(Flex::Center).areas(area);
+
+        let commit = state.get_selected_commit();
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() as i64);
+
+        let field = |label: &str, value: String| Line::from(vec![
+            Span::from(format!("{label:<15}")).bold(),
+            Span::from(value),
+        ]);
+
+        let identity_lines = |role: &str, identity: &Identity| vec![
+            field(&format!("{role}:"), format!("{} <{}>", identity.name, identity.email)),
+            field(
+                &format!("{role}Date:"),
+                format!("{} ({})", format_time(identity), relative_time(identity.time, now)),
+            ),
+        ];
+
+        let mut lines = vec![field("Commit:", commit.hash.clone())];
+
+        if !commit.parents.is_empty() {
+            lines.push(field("Parents:", commit.parents.join(" ")));
+        }
+
+        if let Some(signature) = &commit.signature {
+            let value = match signature {
+                SignatureStatus::Good { signer } => format!("good signature from {signer}"),
+                SignatureStatus::Bad => String::from("bad signature"),
+                SignatureStatus::UnknownKey => String::from("signed with an unknown key"),
+                SignatureStatus::Unverifiable(reason) => format!("signed, but couldn't be verified: {reason}"),
+                SignatureStatus::Unsigned => String::from("unsigned"),
+            };
+
+            lines.push(field("Signature:", value));
+        }
+
+        if let Some(author) = &commit.author {
+            lines.extend(identity_lines("Author", author));
+        }
+
+        if let Some(committer) = &commit.committer {
+            lines.extend(identity_lines("Committer", committer));
+        }
+
+        if let Some(message) = &commit.message {
+            lines.push(Line::from(""));
+            lines.extend(message.lines().map(|line| Line::from(line.to_string())));
+        }
+
+        if let Some(note) = state.review.note(commit.hash.as_str()) {
+            lines.push(Line::from(""));
+            lines.push(Line::from("Review note").bold());
+            lines.extend(note.lines().map(|line| Line::from(format!("  {line}")).yellow()));
+        }
+
+        if !commit.trailers.is_empty() {
+            lines.push(Line::from(""));
+            lines.push(Line::from("Trailers").bold());
+
+            for (key, value) in &commit.trailers {
+                lines.push(Line::from(vec![
+                    Span::from(format!("  {key}: ")).cyan(),
+                    Span::from(value.clone()),
+                ]));
+            }
+        }
+
+        let block = Block::bordered()
+            .title(Line::from(format!(" {} ", commit.short_hash).bold()).centered())
+            .title_bottom(Line::from(" esc to close ").centered())
+            .border_set(border::THICK)
+            .padding(Padding::horizontal(1));
+
+        Clear.render(area, buf);
+
+        Paragraph::new(lines)
+            .block(block)
+            .wrap(Wrap { trim: false })
+            .render(area, buf);
+    }
+}
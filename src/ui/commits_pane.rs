@@ -6,17 +6,22 @@ use ratatui::{
         Modifier, Style, Stylize,
     },
     symbols::border,
-    text::{Line, Text},
+    text::{Line, Span, Text},
     widgets::{Block, HighlightSpacing, List, ListItem, ListState, Scrollbar, ScrollbarOrientation, ScrollbarState, StatefulWidget, Widget},
 };
+use std::time::{SystemTime, UNIX_EPOCH};
 use textwrap::wrap;
 
-use crate::state::{AppState, Pane};
+use crate::signature::SignatureStatus;
+use crate::state::{AppState, CommitKind, Pane};
+use crate::verdict::VerdictKind;
+use crate::ui::format::relative_time;
+use crate::viewed::ViewedStatus;
 
 #[derive(Debug, Default)]
 pub struct CommitsPane {}
 
-impl<'a> StatefulWidget for &'a CommitsPane {
+impl StatefulWidget for &CommitsPane {
     type State = AppState;
 
     fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
@@ -42,6 +47,10 @@ impl<'a> StatefulWidget for &'a CommitsPane {
         let horizontal_padding = 2;
         let wrap_width = total_width.saturating_sub(horizontal_padding);
 
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() as i64);
+
         let items: Vec<ListItem> = state.commits_order
             .iter()
             .map(|hash| {
@@ -49,11 +58,73 @@ impl<'a> StatefulWidget for &'a CommitsPane {
                     panic!("No commit found for hash: {}", hash);
                 };
 
-                let mut parts = vec![
-                    Line::from(""),
-                    Line::from(format!(" {} ", hash.clone())),
-                    Line::from(format!(" {} ", item.author.clone())),
-                ];
+                let viewed = match state.viewed.commit_status(item) {
+                    ViewedStatus::Viewed => Some(Span::from("✓ viewed ").cyan()),
+                    ViewedStatus::ChangedSinceViewed => Some(Span::from("changed since viewed ").yellow()),
+                    ViewedStatus::Unviewed => None,
+                };
+
+                let mut parts = match item.kind {
+                    CommitKind::Commit => {
+                        let mut hash_line = Line::from(format!(" {} ", item.short_hash).yellow());
+
+                        if let Some(author) = &item.author {
+                            hash_line.push_span(format!("{} ", relative_time(author.time, now)).dark_gray());
+                        }
+
+                        if item.is_merge() {
+                            hash_line.push_span(format!("⑂ {} ", item.merge_diff.label()).magenta());
+                        }
+
+                        if let Some(signature) = &item.signature {
+                            let badge = Span::from(format!("{} ", signature.label()));
+
+                            hash_line.push_span(match signature {
+                                SignatureStatus::Good { .. } => badge.green(),
+                                SignatureStatus::Bad => badge.red().bold(),
+                                SignatureStatus::UnknownKey => badge.yellow(),
+                                SignatureStatus::Unverifiable(_) => badge.light_red(),
+                                SignatureStatus::Unsigned => badge.dark_gray(),
+                            });
+                        }
+
+                        if let Some(viewed) = viewed {
+                            hash_line.push_span(viewed);
+                        }
+
+                        let author_name = item.author.as_ref().map_or("", |author| author.name.as_str());
+
+                        let mut lines = vec![
+                            Line::from(""),
+                            hash_line,
+                            Line::from(format!(" {} ", author_name)),
+                        ];
+
+                        for verdict in &item.verdicts {
+                            let badge = Span::from(format!(" {} ", verdict.kind.label()));
+
+                            let mut line = Line::from(match verdict.kind {
+                                VerdictKind::Approved => badge.green(),
+                                VerdictKind::NeedsWork => badge.red(),
+                                VerdictKind::Question => badge.yellow(),
+                            });
+
+                            line.push_span(Span::from(verdict.name.clone()).dark_gray());
+
+                            if !verdict.note.is_empty() {
+                                line.push_span(Span::from(format!(": {}", verdict.note)).dark_gray());
+                            }
+
+                            lines.push(line);
+                        }
+
+                        lines
+                    },
+                    CommitKind::WholeBranch | CommitKind::Staged | CommitKind::Unstaged => vec![
+                        Line::from(""),
+                        Line::from_iter([Span::from(format!(" {} ", item.short_hash)).italic()].into_iter().chain(viewed)),
+                    ],
+                };
 
                 if let Some(msg) = &item.message {
                     for line in msg.lines() {
@@ -69,7 +140,20 @@ impl<'a> StatefulWidget for &'a CommitsPane {
 
                 parts.push(Line::from(""));
 
-                ListItem::new(Text::from(parts))
+                // commits with nothing left to show, e.g. after path filtering, are dimmed, going
+                // by the paths they change until their diff has been computed
+                let is_empty = match item.file_tree {
+                    Some(_) => item.file_count() == 0,
+                    None => item.outside_paths,
+                };
+
+                let text = if is_empty {
+                    Text::from(parts).dark_gray()
+                } else {
+                    Text::from(parts)
+                };
+
+                ListItem::new(text)
             })
         .collect();
 
@@ -86,7 +170,7 @@ impl<'a> StatefulWidget for &'a CommitsPane {
         }
 
         {
-            let mut scroll_state = ScrollbarState::new(state.commits.len())
+            let mut scroll_state = ScrollbarState::new(state.commits_order.len())
                 .position(state.selected_commit);
 
             Scrollbar::new(ScrollbarOrientation::VerticalRight)
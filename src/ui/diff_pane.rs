@@ -1,13 +1,27 @@
 use ratatui::{
-    buffer::Buffer, layout::{Constraint, Direction, Layout, Rect}, style::{Color, Style, Stylize}, symbols::border, text::Line, widgets::{Block, Padding, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState, StatefulWidget, Widget}
+    buffer::Buffer, layout::{Constraint, Direction, Layout, Rect}, style::{palette::tailwind::SLATE, Color, Style, Stylize}, symbols::border, text::{Line, Span}, widgets::{Block, Clear, Padding, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState, StatefulWidget, Widget}
 };
+use std::ops::Range;
 
-use crate::{file_tree::FileTreeFilesItem, state::{AppState, ChangeKind, Commit}};
+use crate::{file_tree::{DiffView, FileTreeFilesItem}, highlight::HighlightedLine, search::Search, state::{AppState, Change, ChangeKind, Commit, Pane}};
+
+const INSERTION_TINT: Color = Color::Rgb(0x1e, 0x3a, 0x24);
+const DELETION_TINT: Color = Color::Rgb(0x45, 0x1e, 0x22);
+const INSERTION_EMPHASIS: Color = Color::Rgb(0x2e, 0x6b, 0x3c);
+const DELETION_EMPHASIS: Color = Color::Rgb(0x8a, 0x2e, 0x38);
 
 #[derive(Debug, Default)]
 pub struct DiffPane {}
 
-impl<'a> StatefulWidget for &'a DiffPane {
+// which line numbers are shown in the gutter, both in the unified view or one per side when split
+#[derive(Debug, Clone, Copy)]
+enum Gutter {
+    Both,
+    Old,
+    New,
+}
+
+impl StatefulWidget for &DiffPane {
     type State = AppState;
 
     /*
@@ -24,24 +38,49 @@ impl<'a> StatefulWidget for &'a DiffPane {
         let outer = Block::new().padding(Padding::uniform(1));
         let inner = outer.inner(area);
 
-        state.scroll_height = inner.height as i16;
+        state.scroll_height = inner.height as i32;
 
         outer.render(area, buf);
 
         let commit = state.get_selected_commit();
 
+        if commit.file_tree.is_none() {
+            Paragraph::new(Line::from("Loading diff…").dark_gray().italic())
+                .render(inner, buf);
+            return;
+        }
+
+        if commit.file_count() == 0 {
+            Paragraph::new(Line::from("No changes to show").dark_gray().italic())
+                .render(inner, buf);
+            return;
+        }
+
         let render_area = DiffPane::render_scroll_layout(commit.diff_len, state.scroll_position, inner, buf);
-        let files_rendered = DiffPane::render_commit_diff(commit, state.scroll_position, render_area, buf);
+        // the cursor's line numbers are highlighted the way the files pane highlights its selection,
+        // along with the rest of the lines selected for a comment
+        let cursor_style = Style::default()
+            .fg(Color::White)
+            .bg(if state.selected_pane == Pane::Diff { SLATE.c600 } else { SLATE.c700 });
+
+        let files_rendered = DiffPane::render_commit_diff(
+            commit,
+            state.search.as_ref(),
+            state.scroll_position,
+            (state.cursor_rows(), cursor_style),
+            render_area,
+            buf,
+        );
 
         // multiply by 2 for top and bottom file borders
         // add 2 for first and last file borders not included in inner.height
-        state.lines_rendered = (inner.height as i16) - (files_rendered as i16) * 2 + 2;
+        state.lines_rendered = (inner.height as i32) - files_rendered * 2 + 2;
     }
 }
 
 impl DiffPane {
-    fn render_scroll_layout(diff_len: usize, scroll_position: i16, render_area: Rect, buf: &mut Buffer) -> Rect {
-        if (diff_len as i16) - (render_area.height as i16) <= 0 {
+    fn render_scroll_layout(diff_len: usize, scroll_position: i32, render_area: Rect, buf: &mut Buffer) -> Rect {
+        if (diff_len as i32) - (render_area.height as i32) <= 0 {
             return render_area;
         }
 
@@ -53,7 +92,7 @@ impl DiffPane {
             ])
             .split(render_area);
 
-        let mut scroll_state = ScrollbarState::new(((diff_len as i16) - (render_area.height as i16)) as usize)
+        let mut scroll_state = ScrollbarState::new(((diff_len as i32) - (render_area.height as i32)) as usize)
             .position(scroll_position as usize);
 
         Scrollbar::new(ScrollbarOrientation::VerticalRight)
@@ -64,17 +103,26 @@ impl DiffPane {
         layout_parts[0]
     }
 
-    fn render_commit_diff(commit: &Commit, scroll_position: i16, render_area: Rect, buf: &mut Buffer) -> i16 {
-        let mut rows_filled: i16 = 0;
-        let mut lines_consumed: i16 = 0;
-        let mut files_rendered: i16 = 0;
-
-        for FileTreeFilesItem { name, changes, .. } in commit.file_tree.iter_files() {
-            if rows_filled >= (render_area.height as i16) {
+    fn render_commit_diff(
+        commit: &Commit,
+        search: Option<&Search>,
+        scroll_position: i32,
+        ((cursor_first, cursor_last), cursor_style): ((usize, usize), Style),
+        render_area: Rect,
+        buf: &mut Buffer,
+    ) -> i32 {
+        let mut rows_filled: i32 = 0;
+        let mut lines_consumed: i32 = 0;
+        let mut files_rendered: i32 = 0;
+
+        for file in commit.file_tree.iter().flat_map(|file_tree| file_tree.iter_files()) {
+            let FileTreeFilesItem { path, old_path, changes, content_kind, .. } = file;
+
+            if rows_filled >= (render_area.height as i32) {
                 break;
             }
 
-            let diff_len = changes.len() as i16;
+            let diff_len = file.line_count() as i32;
 
             if lines_consumed + diff_len <= scroll_position {
                 lines_consumed += diff_len;
@@ -91,39 +139,16 @@ impl DiffPane {
                 diff_len - start_idx + lines_consumed
             } else {
                 diff_len + rows_filled
-            } > (render_area.height as i16);
+            } > (render_area.height as i32);
 
             let num_rows = if truncates && scrolling_inside {
-                std::cmp::min(diff_len - start_idx, render_area.height as i16)
+                std::cmp::min(diff_len - start_idx, render_area.height as i32)
             } else if truncates {
-                (render_area.height as i16) - rows_filled
+                (render_area.height as i32) - rows_filled
             } else {
                 diff_len - start_idx
             };
 
-            let lines: Vec<Line> = changes
-                .iter()
-                .skip(start_idx as usize)
-                .take(num_rows as usize)
-                .map(|change| {
-                    let prefix = match change.kind {
-                        ChangeKind::Context => ' ',
-                        ChangeKind::Insertion => '+',
-                        ChangeKind::Deletion => '-',
-                    };
-
-                    let style = match change.kind {
-                        ChangeKind::Context => Style::default(),
-                        ChangeKind::Insertion => Style::default()
-                            .fg(Color::Green),
-                        ChangeKind::Deletion => Style::default()
-                            .fg(Color::Red),
-                    };
-
-                    Line::styled(format!("{prefix} {}", change.text.clone()), style)
-                })
-                .collect();
-
             // Add 2 for Block's top and bottom borders
             let outer_height = num_rows + 2;
 
@@ -134,17 +159,88 @@ impl DiffPane {
                 width: render_area.width,
             };
 
-            let title = Line::from(name).bold();
+            let mut title = match old_path {
+                Some(old_path) => Line::from(format!("{old_path} → {path}")).bold(),
+                None => Line::from(path).bold(),
+            };
+
+            if let Some((old_mode, new_mode)) = file.mode_change {
+                title.push_span(Span::from(format!(" mode {old_mode:o} → {new_mode:o}")).magenta());
+            }
 
             let block = Block::bordered()
                 .title(title)
                 .border_set(border::PLAIN);
 
-            Paragraph::new(lines)
-                .block(block)
-                .render(space, buf);
+            let inner = block.inner(space);
+            block.render(space, buf);
+
+            let rows = (start_idx as usize..(start_idx + num_rows) as usize)
+                .map(|row| (file.row(row), (cursor_first..=cursor_last).contains(&(file.scroll_start + row))));
 
-            rows_filled += outer_height as i16;
+            let number_width = changes
+                .iter()
+                .flat_map(|change| [change.old_lineno, change.new_lineno])
+                .flatten()
+                .max()
+                .map_or(1, |lineno| lineno.to_string().len());
+
+            let highlights = file.highlights();
+            let emphasis = file.emphasis();
+
+            let change_line = |idx: Option<usize>, gutter: Gutter, at_cursor: bool| match idx {
+                Some(idx) => DiffPane::change_line(
+                    &changes[idx],
+                    highlights.and_then(|highlights| highlights.get(idx)),
+                    &emphasis[idx],
+                    search,
+                    gutter,
+                    number_width,
+                    if at_cursor { cursor_style } else { Style::default().fg(Color::DarkGray) },
+                ),
+                None => Line::from(""),
+            };
+
+            match (content_kind.placeholder(), file.view) {
+                (Some(placeholder), _) => {
+                    Paragraph::new(Line::styled(placeholder, Style::default().fg(Color::DarkGray).italic()))
+                        .render(inner, buf);
+                },
+                (None, DiffView::Unified) => {
+                    Paragraph::new(rows.map(|((change, _), at_cursor)| change_line(change, Gutter::Both, at_cursor)).collect::<Vec<Line>>())
+                        .render(inner, buf);
+                },
+                (None, DiffView::Split) => {
+                    let [old_area, separator_area, new_area] = Layout::horizontal([
+                        Constraint::Fill(1),
+                        Constraint::Length(1),
+                        Constraint::Fill(1),
+                    ]).areas(inner);
+
+                    let rows: Vec<_> = rows.collect();
+
+                    let (old_lines, new_lines): (Vec<Line>, Vec<Line>) = rows
+                        .iter()
+                        .map(|&((old, new), at_cursor)| (change_line(old, Gutter::Old, at_cursor), change_line(new, Gutter::New, at_cursor)))
+                        .unzip();
+
+                    Paragraph::new(old_lines).render(old_area, buf);
+                    Paragraph::new(vec![Line::from("│").dark_gray(); num_rows as usize]).render(separator_area, buf);
+                    Paragraph::new(new_lines).render(new_area, buf);
+
+                    // hunk headers and comments are drawn across both sides
+                    for (offset, &((old, _), at_cursor)) in rows.iter().enumerate() {
+                        if let Some(idx) = old && matches!(changes[idx].kind, ChangeKind::HunkHeader | ChangeKind::Comment) {
+                            let header_area = Rect { y: inner.y + offset as u16, height: 1, ..inner };
+
+                            Clear.render(header_area, buf);
+                            Paragraph::new(change_line(old, Gutter::Both, at_cursor)).render(header_area, buf);
+                        }
+                    }
+                },
+            }
+
+            rows_filled += outer_height as i32;
             files_rendered += 1;
             if scrolling_inside {
                 lines_consumed += diff_len - num_rows;
@@ -153,4 +249,115 @@ impl DiffPane {
 
         files_rendered
     }
+
+    fn change_line<'a>(
+        change: &'a Change,
+        highlight: Option<&HighlightedLine>,
+        emphasis: &[Range<usize>],
+        search: Option<&Search>,
+        gutter: Gutter,
+        number_width: usize,
+        gutter_style: Style,
+    ) -> Line<'a> {
+        let lineno = |lineno: Option<u32>| lineno.map_or(String::new(), |lineno| lineno.to_string());
+
+        let numbers = match gutter {
+            Gutter::Both => format!(
+                "{:>number_width$} {:>number_width$} ",
+                lineno(change.old_lineno),
+                lineno(change.new_lineno),
+            ),
+            Gutter::Old => format!("{:>number_width$} ", lineno(change.old_lineno)),
+            Gutter::New => format!("{:>number_width$} ", lineno(change.new_lineno)),
+        };
+
+        let mut line = Line::from(Span::styled(numbers, gutter_style));
+
+        match change.kind {
+            ChangeKind::HunkHeader => {
+                line.push_span(Span::from(change.text.as_str()).cyan());
+                return line;
+            },
+            ChangeKind::Comment => {
+                line.push_span(Span::from("┃ ").yellow());
+                line.push_span(Span::from(change.text.as_str()).italic());
+                return line;
+            },
+            _ => {},
+        }
+
+        // insertions and deletions are tinted, leaving the foreground to syntax highlighting
+        let (prefix, style) = match (change.kind, highlight) {
+            (ChangeKind::Insertion, Some(_)) => ('+', Style::default().bg(INSERTION_TINT)),
+            (ChangeKind::Deletion, Some(_)) => ('-', Style::default().bg(DELETION_TINT)),
+            (ChangeKind::Insertion, None) => ('+', Style::default().fg(Color::Green).bg(INSERTION_TINT)),
+            (ChangeKind::Deletion, None) => ('-', Style::default().fg(Color::Red).bg(DELETION_TINT)),
+            _ => (' ', Style::default()),
+        };
+
+        let text = change.text.trim_end_matches('\n');
+
+        let syntax = highlight
+            .into_iter()
+            .flatten()
+            .map(|(color, range)| (range.clone(), Style::default().fg(*color)));
+
+        // the words that changed from the paired line get a stronger tint
+        let emphasis_style = match change.kind {
+            ChangeKind::Deletion => Style::default().bg(DELETION_EMPHASIS),
+            _ => Style::default().bg(INSERTION_EMPHASIS),
+        };
+
+        let emphasis = emphasis
+            .iter()
+            .map(|range| (range.clone(), emphasis_style));
+
+        let matches = search
+            .map(|search| search.find_in(change))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|range| (range, Style::default().fg(Color::Black).bg(Color::Yellow)));
+
+        // the gutter keeps its own color, everything else takes the line's
+        line = line.style(style);
+        line.push_span(Span::from(format!("{prefix} ")));
+        line.extend(DiffPane::styled_spans(text, &syntax.chain(emphasis).chain(matches).collect::<Vec<_>>()));
+
+        // flag lines that had to be decoded with something other than UTF-8
+        if let Some(encoding) = change.encoding {
+            line.push_span(Span::styled(format!(" [{encoding}]"), Style::default().fg(Color::DarkGray).italic()));
+        }
+
+        line
+    }
+
+    /*
+     * Splits text into spans wherever one of the styled ranges starts or ends. Each span
+     * takes the styles of every range covering it, with later ranges taking precedence.
+     * */
+    fn styled_spans<'a>(text: &'a str, layers: &[(Range<usize>, Style)]) -> Vec<Span<'a>> {
+        let mut boundaries: Vec<usize> = layers
+            .iter()
+            .flat_map(|(range, _)| [range.start, range.end])
+            .chain([0, text.len()])
+            .filter(|&boundary| boundary <= text.len() && text.is_char_boundary(boundary))
+            .collect();
+
+        boundaries.sort_unstable();
+        boundaries.dedup();
+
+        boundaries
+            .windows(2)
+            .map(|window| {
+                let (start, end) = (window[0], window[1]);
+
+                let style = layers
+                    .iter()
+                    .filter(|(range, _)| range.start <= start && end <= range.end)
+                    .fold(Style::default(), |style, (_, layer)| style.patch(*layer));
+
+                Span::styled(&text[start..end], style)
+            })
+            .collect()
+    }
 }
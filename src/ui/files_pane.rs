@@ -3,11 +3,11 @@ use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{palette::tailwind::SLATE, Color, Modifier, Style, Stylize},
     symbols::border,
-    text::Line,
+    text::{Line, Span},
     widgets::{Block, HighlightSpacing, List, ListItem, ListState, Scrollbar, ScrollbarOrientation, ScrollbarState, StatefulWidget, Widget},
 };
 
-use crate::{file_tree::{FileChangeKind, FileTree, FileTreeItem}, state::{AppState, Pane}};
+use crate::{file_tree::{FileChangeKind, FileTree, FileTreeItem}, state::{AppState, Pane}, viewed::ViewedStatus};
 
 #[derive(Debug, Default)]
 pub struct FilesPane {}
@@ -39,7 +39,9 @@ impl StatefulWidget for &FilesPane {
         let mut lines: Vec<ListItem> = Vec::new();
         let mut selectable_indices: Vec<usize> = Vec::new();
 
-        for (idx, FileTreeItem { node, depth }) in commit.file_tree.iter().enumerate() {
+        let nodes = commit.file_tree.iter().flat_map(|file_tree| file_tree.iter());
+
+        for (idx, FileTreeItem { node, depth }) in nodes.enumerate() {
             let indent = " ".repeat(depth * 2);
 
             let prefix = match node {
@@ -48,6 +50,8 @@ impl StatefulWidget for &FilesPane {
                     FileChangeKind::Change => '*',
                     FileChangeKind::Creation => '+',
                     FileChangeKind::Deletion => '-',
+                    FileChangeKind::Rename => '>',
+                    FileChangeKind::Copy => '=',
                 },
             };
 
@@ -57,10 +61,25 @@ impl StatefulWidget for &FilesPane {
                     FileChangeKind::Change => Style::default().fg(Color::Yellow),
                     FileChangeKind::Creation => Style::default().fg(Color::Green),
                     FileChangeKind::Deletion => Style::default().fg(Color::Red),
+                    FileChangeKind::Rename | FileChangeKind::Copy => Style::default().fg(Color::Cyan),
                 },
             };
 
-            let line = Line::styled(format!("{indent}{prefix} {}", node.name()), style);
+            let name = match node {
+                FileTree::File { old_path: Some(old_path), .. } => format!("{old_path} → {}", node.name()),
+                _ => node.name().clone(),
+            };
+
+            let mut line = Line::styled(format!("{indent}{prefix} {name}"), style);
+
+            if let FileTree::File { path, blob_ids, .. } = node {
+                match state.viewed.file_status(commit, path, blob_ids) {
+                    ViewedStatus::Viewed => line.push_span(Span::from(" ✓").cyan()),
+                    ViewedStatus::ChangedSinceViewed => line.push_span(Span::from(" changed since viewed").yellow()),
+                    ViewedStatus::Unviewed => {},
+                }
+            }
+
             lines.push(ListItem::new(line));
 
             if let FileTree::File { .. } = node {
@@ -71,15 +90,14 @@ impl StatefulWidget for &FilesPane {
         block.render(area, buf);
 
         let list = List::new(lines)
-            .highlight_style(Style::new().bg(SLATE.c600).add_modifier(Modifier::BOLD))
+            .highlight_style(Style::new().bg(if has_focus { SLATE.c600 } else { SLATE.c700 }).add_modifier(Modifier::BOLD))
             .highlight_spacing(HighlightSpacing::Always);
 
         {
             let mut list_state = ListState::default();
 
-            if state.selected_pane == Pane::Files {
-                list_state.select(Some(selectable_indices[state.selected_file]));
-            }
+            // follows the diff pane's viewport while the diff pane has focus
+            list_state.select(selectable_indices.get(state.selected_file).copied());
 
             StatefulWidget::render(&list, layout_parts[0], buf, &mut list_state);
         }
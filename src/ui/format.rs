@@ -0,0 +1,118 @@
+use crate::state::Identity;
+
+const MINUTE: i64 = 60;
+const HOUR: i64 = 60 * MINUTE;
+const DAY: i64 = 24 * HOUR;
+const WEEK: i64 = 7 * DAY;
+const MONTH: i64 = 30 * DAY;
+const YEAR: i64 = 365 * DAY;
+
+// e.g. "3 days ago", in the style of `git log --date=relative`
+pub fn relative_time(time: i64, now: i64) -> String {
+    let elapsed = now - time;
+
+    if elapsed < 0 {
+        return String::from("in the future");
+    }
+
+    let (count, unit) = match elapsed {
+        e if e < MINUTE => (e, "second"),
+        e if e < HOUR => (e / MINUTE, "minute"),
+        e if e < DAY => (e / HOUR, "hour"),
+        e if e < 2 * WEEK => (e / DAY, "day"),
+        e if e < 2 * MONTH => (e / WEEK, "week"),
+        e if e < YEAR => (e / MONTH, "month"),
+        e => (e / YEAR, "year"),
+    };
+
+    format!("{count} {unit}{} ago", if count == 1 { "" } else { "s" })
+}
+
+// e.g. "2025-03-14 09:26:53 +0100", in the identity's own timezone
+pub fn format_time(identity: &Identity) -> String {
+    let local = identity.time + identity.offset_minutes as i64 * MINUTE;
+
+    let (year, month, day) = civil_from_days(local.div_euclid(DAY));
+    let seconds = local.rem_euclid(DAY);
+
+    let sign = if identity.offset_minutes < 0 { '-' } else { '+' };
+    let offset = identity.offset_minutes.abs();
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} {sign}{:02}{:02}",
+        seconds / HOUR,
+        seconds % HOUR / MINUTE,
+        seconds % MINUTE,
+        offset / 60,
+        offset % 60,
+    )
+}
+
+/*
+ * Converts days since 1970-01-01 to a (year, month, day) date in the proleptic Gregorian
+ * calendar. See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+ * */
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: i64, offset_minutes: i32) -> Identity {
+        Identity {
+            name: String::from("Ada"),
+            email: String::from("ada@example.com"),
+            time,
+            offset_minutes,
+        }
+    }
+
+    #[test]
+    fn epoch() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(format_time(&at(0, 0)), "1970-01-01 00:00:00 +0000");
+    }
+
+    #[test]
+    fn leap_day() {
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(11017), (2000, 3, 1));
+        assert_eq!(format_time(&at(951782400 + 12 * HOUR, 60)), "2000-02-29 13:00:00 +0100");
+    }
+
+    #[test]
+    fn before_the_epoch() {
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(format_time(&at(-14182940, 0)), "1969-07-20 20:17:40 +0000");
+    }
+
+    #[test]
+    fn negative_offset() {
+        assert_eq!(format_time(&at(0, -300)), "1969-12-31 19:00:00 -0500");
+        assert_eq!(format_time(&at(951782400, -90)), "2000-02-28 22:30:00 -0130");
+    }
+
+    #[test]
+    fn relative_dates() {
+        let now = 1_700_000_000;
+
+        assert_eq!(relative_time(now, now), "0 seconds ago");
+        assert_eq!(relative_time(now - MINUTE, now), "1 minute ago");
+        assert_eq!(relative_time(now - 3 * DAY - HOUR, now), "3 days ago");
+        assert_eq!(relative_time(now - 3 * WEEK, now), "3 weeks ago");
+        assert_eq!(relative_time(now - 2 * YEAR, now), "2 years ago");
+        assert_eq!(relative_time(now + 1, now), "in the future");
+    }
+}
@@ -2,6 +2,8 @@ pub mod bottom_bar;
 pub mod diff_pane;
 pub mod files_pane;
 pub mod commits_pane;
+pub mod commit_details;
+pub mod format;
 
 use crate::state::AppState;
 use crate::ui::{
@@ -9,6 +11,7 @@ use crate::ui::{
     diff_pane::DiffPane,
     files_pane::FilesPane,
     commits_pane::CommitsPane,
+    commit_details::CommitDetails,
 };
 
 use ratatui::{
@@ -22,9 +25,10 @@ pub struct UI {
     files_pane: FilesPane,
     commits_pane: CommitsPane,
     bottom_bar: BottomBar,
+    commit_details: CommitDetails,
 }
 
-impl<'a> UI {
+impl UI {
     pub fn render(&mut self, frame: &mut Frame, state: &mut AppState) {
         let outer_layout = Layout::default()
             .direction(Direction::Vertical)
@@ -54,5 +58,9 @@ impl<'a> UI {
         frame.render_stateful_widget(&self.files_pane, right_layout[0], state);
         frame.render_stateful_widget(&self.commits_pane, right_layout[1], state);
         frame.render_stateful_widget(&self.bottom_bar, outer_layout[1], state);
+
+        if state.show_commit_details {
+            frame.render_stateful_widget(&self.commit_details, outer_layout[0], state);
+        }
     }
 }
@@ -0,0 +1,139 @@
+use crate::review::{escape, unescape};
+
+// the notes ref verdicts are kept under, shared by pushing and fetching it like any other ref
+pub const NOTES_REF: &str = "refs/notes/branch-diff";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerdictKind {
+    Approved,
+    NeedsWork,
+    Question,
+}
+
+// one reviewer's verdict on a commit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verdict {
+    pub kind: VerdictKind,
+    pub name: String,
+    // reviewers are told apart by email, so each has at most one verdict per commit
+    pub email: String,
+    pub note: String,
+}
+
+impl VerdictKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            VerdictKind::Approved => "✔ approved",
+            VerdictKind::NeedsWork => "✘ needs work",
+            VerdictKind::Question => "? question",
+        }
+    }
+
+    // the verdict the prompt moves on to, with None for taking one's verdict back
+    pub fn next(kind: Option<Self>) -> Option<Self> {
+        match kind {
+            Some(VerdictKind::Approved) => Some(VerdictKind::NeedsWork),
+            Some(VerdictKind::NeedsWork) => Some(VerdictKind::Question),
+            Some(VerdictKind::Question) => None,
+            None => Some(VerdictKind::Approved),
+        }
+    }
+
+    fn key(&self) -> &'static str {
+        match self {
+            VerdictKind::Approved => "approved",
+            VerdictKind::NeedsWork => "needs-work",
+            VerdictKind::Question => "question",
+        }
+    }
+
+    fn parse(key: &str) -> Option<Self> {
+        match key {
+            "approved" => Some(VerdictKind::Approved),
+            "needs-work" => Some(VerdictKind::NeedsWork),
+            "question" => Some(VerdictKind::Question),
+            _ => None,
+        }
+    }
+}
+
+/*
+ * Reads the verdicts in a commit's note, one per line with tab separated fields:
+ *
+ *   <approved|needs-work|question>  <name>  <email>  <note>
+ *
+ * Lines stay independent so notes written on different machines can be combined with
+ * `git notes merge -s cat_sort_uniq`. Lines that can't be read are skipped.
+ * */
+pub fn parse_note(message: &str) -> Vec<Verdict> {
+    message
+        .lines()
+        .filter_map(|line| {
+            let [kind, name, email, note] = line.split('\t').collect::<Vec<&str>>()[..] else {
+                return None;
+            };
+
+            Some(Verdict {
+                kind: VerdictKind::parse(kind)?,
+                name: unescape(name),
+                email: unescape(email),
+                note: unescape(note),
+            })
+        })
+        .collect()
+}
+
+pub fn format_note(verdicts: &[Verdict]) -> String {
+    verdicts
+        .iter()
+        .map(|verdict| format!(
+            "{}\t{}\t{}\t{}\n",
+            verdict.kind.key(),
+            escape(verdict.name.as_str()),
+            escape(verdict.email.as_str()),
+            escape(verdict.note.as_str()),
+        ))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn verdict(kind: VerdictKind, name: &str, note: &str) -> Verdict {
+        Verdict {
+            kind,
+            name: name.to_string(),
+            email: format!("{}@example.com", name.to_lowercase()),
+            note: note.to_string(),
+        }
+    }
+
+    #[test]
+    fn several_reviewers_round_trip() {
+        let verdicts = vec![
+            verdict(VerdictKind::Approved, "Ada", ""),
+            verdict(VerdictKind::NeedsWork, "Grace", "the error\tisn't handled\nin two places"),
+            verdict(VerdictKind::Question, "Linus", "why not C:\\temp?"),
+        ];
+
+        let note = format_note(&verdicts);
+
+        assert_eq!(note.lines().count(), 3);
+        assert_eq!(parse_note(&note), verdicts);
+    }
+
+    #[test]
+    fn unknown_verdicts_are_skipped() {
+        let note = "approved\tAda\tada@example.com\t\nrejected\tGrace\tgrace@example.com\tno\napproved\tmissing fields\n";
+
+        assert_eq!(parse_note(note), [verdict(VerdictKind::Approved, "Ada", "")]);
+    }
+
+    #[test]
+    fn empty_note_has_no_verdicts() {
+        assert!(parse_note("").is_empty());
+        assert!(parse_note("\n\n").is_empty());
+        assert_eq!(format_note(&[]), "");
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::file_tree::BlobIds;
+use crate::state::{Commit, CommitKind};
+
+/*
+ * Files and commits the reviewer has marked as viewed, kept in `.git/branch-diff/viewed` so
+ * they last between sessions. A file is marked by the blob it was viewed at, so it stays
+ * viewed when its commit is rebased without touching it. Each mark also records the hash
+ * and subject of the commit it was made in, which is how a file changed by a rewritten
+ * version of that commit is told apart from one that was never viewed.
+ * */
+#[derive(Debug)]
+pub struct ViewedMarks {
+    path: PathBuf,
+    commits: BTreeSet<String>,
+    files: Vec<FileMark>,
+    // hashes of the commits being reviewed, whose marks belong to them alone
+    listed: HashSet<String>,
+}
+
+#[derive(Debug)]
+struct FileMark {
+    // the blob the file was left as, or for a deletion the blob it was deleted from
+    version: String,
+    commit: String,
+    subject: String,
+    path: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViewedStatus {
+    Unviewed,
+    Viewed,
+    // viewed in an earlier version of the commit, but different now
+    ChangedSinceViewed,
+}
+
+impl ViewedMarks {
+    pub fn new(git_dir: &Path) -> Self {
+        ViewedMarks {
+            path: git_dir.join("branch-diff").join("viewed"),
+            commits: BTreeSet::new(),
+            files: Vec::new(),
+            listed: HashSet::new(),
+        }
+    }
+
+    /*
+     * Reads the marks saved in the repository's git directory, one per line with tab
+     * separated fields:
+     *
+     *   commit  <hash>
+     *   file    <blob id>  <commit hash>  <path>  <commit subject>
+     * */
+    pub fn load(git_dir: &Path) -> io::Result<Self> {
+        let mut marks = ViewedMarks::new(git_dir);
+
+        let contents = match fs::read_to_string(&marks.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(marks),
+            Err(e) => return Err(e),
+        };
+
+        for line in contents.lines() {
+            // the subject comes last, so it's the only field that may hold a tab
+            match line.splitn(5, '\t').collect::<Vec<&str>>().as_slice() {
+                ["commit", hash] => {
+                    marks.commits.insert(hash.to_string());
+                },
+                ["file", version, commit, path, subject] => marks.files.push(FileMark {
+                    version: version.to_string(),
+                    commit: commit.to_string(),
+                    subject: subject.to_string(),
+                    path: path.to_string(),
+                }),
+                _ => tracing::error!("skipping malformed line in {}: {line}", marks.path.display()),
+            }
+        }
+
+        Ok(marks)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let commits = self.commits.iter().map(|hash| format!("commit\t{hash}\n"));
+
+        let files = self.files.iter().map(|mark| format!(
+            "file\t{}\t{}\t{}\t{}\n",
+            mark.version,
+            mark.commit,
+            mark.path,
+            mark.subject,
+        ));
+
+        fs::write(&self.path, commits.chain(files).collect::<String>())
+    }
+
+    pub fn set_listed(&mut self, hashes: impl IntoIterator<Item = String>) {
+        self.listed = hashes.into_iter().collect();
+    }
+
+    pub fn file_status(&self, commit: &Commit, path: &str, blob_ids: &BlobIds) -> ViewedStatus {
+        let Some(version) = version(blob_ids) else {
+            return ViewedStatus::Unviewed;
+        };
+
+        let path_marks = || self.files.iter().filter(|mark| mark.path == path);
+
+        if path_marks().any(|mark| mark.version == version) {
+            ViewedStatus::Viewed
+        } else if path_marks().any(|mark| is_same_commit(&self.listed, mark, commit)) {
+            ViewedStatus::ChangedSinceViewed
+        } else {
+            ViewedStatus::Unviewed
+        }
+    }
+
+    /*
+     * A commit is viewed once it's been marked as a whole or all of its files have been.
+     * If any of its files changed since they were viewed, so has the commit. The staged and
+     * unstaged entries keep the same hash whatever they hold, so only their files count.
+     * */
+    pub fn commit_status(&self, commit: &Commit) -> ViewedStatus {
+        let statuses: Vec<ViewedStatus> = commit.file_tree
+            .iter()
+            .flat_map(|file_tree| file_tree.iter_files())
+            .map(|file| self.file_status(commit, file.path, file.blob_ids))
+            .collect();
+
+        if statuses.contains(&ViewedStatus::ChangedSinceViewed) {
+            ViewedStatus::ChangedSinceViewed
+        } else if (self.commits.contains(&commit.hash) && !is_pseudo_commit(commit))
+            || (!statuses.is_empty() && statuses.iter().all(|status| *status == ViewedStatus::Viewed))
+        {
+            ViewedStatus::Viewed
+        } else {
+            ViewedStatus::Unviewed
+        }
+    }
+
+    // the number of the commit's files that are viewed, and how many files it has
+    pub fn file_progress(&self, commit: &Commit) -> (usize, usize) {
+        commit.file_tree
+            .iter()
+            .flat_map(|file_tree| file_tree.iter_files())
+            .fold((0, 0), |(viewed, total), file| {
+                match self.file_status(commit, file.path, file.blob_ids) {
+                    ViewedStatus::Viewed => (viewed + 1, total + 1),
+                    _ => (viewed, total + 1),
+                }
+            })
+    }
+
+    // files without a blob on either side, e.g. untracked ones, can't be marked
+    pub fn toggle_file(&mut self, commit: &Commit, path: &str, blob_ids: &BlobIds) -> io::Result<()> {
+        let Some(version) = version(blob_ids) else {
+            return Ok(());
+        };
+
+        if self.file_status(commit, path, blob_ids) == ViewedStatus::Viewed {
+            self.files.retain(|mark| !(mark.path == path && mark.version == version));
+            self.commits.remove(&commit.hash);
+        } else {
+            self.mark_file(commit, path, version);
+        }
+
+        self.save()
+    }
+
+    // marks or unmarks the commit along with each of its files
+    pub fn toggle_commit(&mut self, commit: &Commit) -> io::Result<()> {
+        let files: Vec<(String, String)> = commit.file_tree
+            .iter()
+            .flat_map(|file_tree| file_tree.iter_files())
+            .filter_map(|file| Some((file.path.to_string(), version(file.blob_ids)?)))
+            .collect();
+
+        if self.commit_status(commit) == ViewedStatus::Viewed {
+            self.commits.remove(&commit.hash);
+
+            for (path, version) in files {
+                self.files.retain(|mark| !(mark.path == path && mark.version == version));
+            }
+        } else {
+            if !is_pseudo_commit(commit) {
+                self.commits.insert(commit.hash.clone());
+            }
+
+            for (path, version) in files {
+                self.mark_file(commit, path.as_str(), version);
+            }
+        }
+
+        self.save()
+    }
+
+    fn mark_file(&mut self, commit: &Commit, path: &str, version: String) {
+        // replaces the mark left by an earlier version of the commit, if there is one
+        let listed = &self.listed;
+        self.files.retain(|mark| !(mark.path == path && is_same_commit(listed, mark, commit)));
+
+        self.files.push(FileMark {
+            version,
+            commit: commit.hash.clone(),
+            subject: subject(commit).to_string(),
+            path: path.to_string(),
+        });
+    }
+}
+
+/*
+ * A commit keeps its subject when it's rebased or amended, but not its hash. Subjects such
+ * as "wip" are often repeated though, so a mark only carries over by subject once the
+ * commit it was made in is no longer listed.
+ * */
+fn is_same_commit(listed: &HashSet<String>, mark: &FileMark, commit: &Commit) -> bool {
+    mark.commit == commit.hash
+        || (!listed.contains(&mark.commit) && !mark.subject.is_empty() && mark.subject == subject(commit))
+}
+
+fn is_pseudo_commit(commit: &Commit) -> bool {
+    matches!(commit.kind, CommitKind::Staged | CommitKind::Unstaged)
+}
+
+fn subject(commit: &Commit) -> &str {
+    commit.message.as_deref().and_then(|message| message.lines().next()).unwrap_or_default()
+}
+
+fn version(blob_ids: &BlobIds) -> Option<String> {
+    // working tree files that git hasn't hashed have an all-zero id
+    let known = |id: &String| !id.bytes().all(|byte| byte == b'0');
+
+    match (&blob_ids.old, &blob_ids.new) {
+        (_, Some(new)) => Some(new.clone()).filter(known),
+        // kept apart from the blob itself, so viewing a deletion doesn't mark the file it deleted
+        (Some(old), None) => Some(format!("-{old}")).filter(|_| known(old)),
+        (None, None) => None,
+    }
+}
@@ -0,0 +1,169 @@
+use std::ops::Range;
+
+// lines with more tokens than this aren't compared, as the comparison grows quadratically
+const MAX_TOKENS: usize = 512;
+// below this share of unchanged text the lines are treated as unrelated
+const MIN_SIMILARITY: f32 = 0.4;
+
+// byte ranges within a line that differ from the line it's compared with
+pub type ChangedRanges = Vec<Range<usize>>;
+
+/*
+ * Compares a deleted line with the inserted line it was paired with, token by token, and
+ * returns the byte ranges that differ on the old and new side. Tokens are runs of word
+ * characters, runs of whitespace, or single punctuation characters. Returns None when the
+ * lines have too little in common for the differences to be worth pointing out.
+ * */
+pub fn changed_ranges(old: &str, new: &str) -> Option<(ChangedRanges, ChangedRanges)> {
+    let old_tokens = tokenize(old);
+    let new_tokens = tokenize(new);
+
+    if old_tokens.len() > MAX_TOKENS || new_tokens.len() > MAX_TOKENS {
+        return None;
+    }
+
+    let (old_common, new_common) = common_tokens(old, &old_tokens, new, &new_tokens);
+
+    let common_len: usize = old_tokens
+        .iter()
+        .zip(&old_common)
+        .filter(|(_, common)| **common)
+        .map(|(token, _)| token.len())
+        .sum();
+
+    let longest = old.len().max(new.len());
+
+    if longest == 0 || (common_len as f32) / (longest as f32) < MIN_SIMILARITY {
+        return None;
+    }
+
+    Some((
+        merge_changed(&old_tokens, &old_common),
+        merge_changed(&new_tokens, &new_common),
+    ))
+}
+
+fn tokenize(text: &str) -> Vec<Range<usize>> {
+    let mut tokens: Vec<Range<usize>> = Vec::new();
+    let mut previous_class = None;
+
+    for (idx, c) in text.char_indices() {
+        let class = if c.is_alphanumeric() || c == '_' {
+            Some(0)
+        } else if c.is_whitespace() {
+            Some(1)
+        } else {
+            // punctuation never joins up with its neighbours
+            None
+        };
+
+        match tokens.last_mut() {
+            Some(token) if class.is_some() && class == previous_class => token.end = idx + c.len_utf8(),
+            _ => tokens.push(idx..idx + c.len_utf8()),
+        }
+
+        previous_class = class;
+    }
+
+    tokens
+}
+
+// marks the tokens on each side that are part of the longest common subsequence
+fn common_tokens(old: &str, old_tokens: &[Range<usize>], new: &str, new_tokens: &[Range<usize>]) -> (Vec<bool>, Vec<bool>) {
+    let (rows, cols) = (old_tokens.len(), new_tokens.len());
+    let mut lengths = vec![0u16; (rows + 1) * (cols + 1)];
+    let at = |row: usize, col: usize| row * (cols + 1) + col;
+
+    for row in (0..rows).rev() {
+        for col in (0..cols).rev() {
+            lengths[at(row, col)] = if old[old_tokens[row].clone()] == new[new_tokens[col].clone()] {
+                lengths[at(row + 1, col + 1)] + 1
+            } else {
+                lengths[at(row + 1, col)].max(lengths[at(row, col + 1)])
+            };
+        }
+    }
+
+    let mut old_common = vec![false; rows];
+    let mut new_common = vec![false; cols];
+    let (mut row, mut col) = (0, 0);
+
+    while row < rows && col < cols {
+        if old[old_tokens[row].clone()] == new[new_tokens[col].clone()] {
+            old_common[row] = true;
+            new_common[col] = true;
+            row += 1;
+            col += 1;
+        } else if lengths[at(row + 1, col)] >= lengths[at(row, col + 1)] {
+            row += 1;
+        } else {
+            col += 1;
+        }
+    }
+
+    (old_common, new_common)
+}
+
+// joins adjacent changed tokens into single ranges
+fn merge_changed(tokens: &[Range<usize>], common: &[bool]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+
+    for (token, _) in tokens.iter().zip(common).filter(|(_, common)| !**common) {
+        match ranges.last_mut() {
+            Some(range) if range.end == token.start => range.end = token.end,
+            _ => ranges.push(token.clone()),
+        }
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the changed text on each side, which also checks the ranges fall on character boundaries
+    fn changed_text<'a>(old: &'a str, new: &'a str) -> Option<(Vec<&'a str>, Vec<&'a str>)> {
+        let (old_ranges, new_ranges) = changed_ranges(old, new)?;
+
+        Some((
+            old_ranges.into_iter().map(|range| &old[range]).collect(),
+            new_ranges.into_iter().map(|range| &new[range]).collect(),
+        ))
+    }
+
+    #[test]
+    fn identical_lines_have_no_changes() {
+        assert_eq!(changed_ranges("let x = 1;", "let x = 1;"), Some((vec![], vec![])));
+    }
+
+    #[test]
+    fn single_token_change() {
+        assert_eq!(changed_text("let x = 1;", "let y = 1;"), Some((vec!["x"], vec!["y"])));
+    }
+
+    #[test]
+    fn insertion_within_a_line() {
+        assert_eq!(changed_text("call(first)", "call(first, second)"), Some((vec![], vec![", second"])));
+    }
+
+    #[test]
+    fn deletion_within_a_line() {
+        assert_eq!(changed_text("call(first, second)", "call(first)"), Some((vec![", second"], vec![])));
+    }
+
+    #[test]
+    fn multibyte_characters_stay_whole() {
+        assert_eq!(changed_text("naïve café au lait", "naïve cafe au lait"), Some((vec!["café"], vec!["cafe"])));
+
+        let (old, new) = changed_ranges("naïve café", "naïve cafe").unwrap();
+        assert_eq!((old.len(), new.len()), (1, 1));
+        assert_eq!((old[0].clone(), new[0].clone()), (7..12, 7..11));
+    }
+
+    #[test]
+    fn unrelated_lines_are_not_compared() {
+        assert_eq!(changed_ranges("fn main() {", "    return values;"), None);
+        assert_eq!(changed_ranges("", ""), None);
+    }
+}
@@ -0,0 +1,234 @@
+use encoding_rs::Encoding;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+use crate::commit_filter::CommitFilter;
+use crate::diff_settings::DiffSettings;
+use crate::file_tree::FileTree;
+use crate::repo::{Repo, RepoError};
+use crate::signature::{SignatureStatus, SignatureVerifier};
+use crate::state::{CommitKind, MergeDiff};
+
+/*
+ * Computes commit diffs on a background thread so the UI never blocks on git. The thread
+ * opens its own repository, takes requests off a queue in the order they were made and
+ * sends each result back over a channel for the event loop to pick up.
+ * */
+#[derive(Debug)]
+pub struct DiffWorker {
+    requests: Sender<DiffRequest>,
+    results: Receiver<DiffResult>,
+    thread: Option<JoinHandle<Result<(), RepoError>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DiffRequest {
+    pub hash: String,
+    pub kind: CommitKind,
+    pub merge_diff: MergeDiff,
+    pub diff_settings: DiffSettings,
+}
+
+#[derive(Debug)]
+pub struct DiffResult {
+    pub request: DiffRequest,
+    pub file_tree: Result<FileTree, RepoError>,
+}
+
+/*
+ * Verifies the signature of every commit in the range on its own thread, since each one
+ * shells out to gpg or ssh-keygen. Results come back in the order the hashes were given.
+ * */
+#[derive(Debug)]
+pub struct SignatureWorker {
+    results: Receiver<SignatureResult>,
+    thread: Option<JoinHandle<Result<(), RepoError>>>,
+}
+
+#[derive(Debug)]
+pub struct SignatureResult {
+    pub hash: String,
+    pub status: Result<SignatureStatus, RepoError>,
+}
+
+/*
+ * Checks which commits match the path and `-S` terms of a commit filter, which need each
+ * commit's diff. Dropping the worker stops the search.
+ * */
+#[derive(Debug)]
+pub struct FilterWorker {
+    results: Receiver<FilterResult>,
+    thread: Option<JoinHandle<Result<(), RepoError>>>,
+}
+
+#[derive(Debug)]
+pub struct FilterResult {
+    pub hash: String,
+    pub matched: Result<bool, RepoError>,
+}
+
+/*
+ * Finds the commits with no changes in the paths given on the command line, so they can be
+ * dimmed before their diffs are computed. Results come back in the order the hashes were given.
+ * */
+#[derive(Debug)]
+pub struct PathspecWorker {
+    results: Receiver<PathspecResult>,
+    thread: Option<JoinHandle<Result<(), RepoError>>>,
+}
+
+#[derive(Debug)]
+pub struct PathspecResult {
+    pub hash: String,
+    pub touched: Result<bool, RepoError>,
+}
+
+/*
+ * A worker's thread has stopped, with the reason it gave. Until every result has been
+ * received this means it failed, e.g. because it couldn't open the repository.
+ * */
+#[derive(Debug)]
+pub struct Disconnected(pub String);
+
+impl DiffWorker {
+    pub fn spawn(fallback_encoding: &'static Encoding, pathspecs: Vec<String>) -> Self {
+        let (request_tx, request_rx) = mpsc::channel::<DiffRequest>();
+        let (result_tx, result_rx) = mpsc::channel::<DiffResult>();
+
+        let thread = thread::spawn(move || {
+            let mut repository = Repo::new(fallback_encoding, pathspecs)?;
+
+            // the loop ends once the app drops its end of the request channel
+            for request in request_rx {
+                repository.set_diff_settings(request.diff_settings);
+
+                let file_tree = repository.commit_diff(request.hash.as_str(), request.kind, request.merge_diff);
+
+                if result_tx.send(DiffResult { request, file_tree }).is_err() {
+                    break;
+                }
+            }
+
+            Ok(())
+        });
+
+        DiffWorker {
+            requests: request_tx,
+            results: result_rx,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn request(&self, request: DiffRequest) {
+        if self.requests.send(request).is_err() {
+            tracing::error!("diff worker has stopped, request dropped");
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Result<Option<DiffResult>, Disconnected> {
+        receive(&self.results, &mut self.thread)
+    }
+}
+
+impl SignatureWorker {
+    pub fn spawn(fallback_encoding: &'static Encoding, verifier: SignatureVerifier, hashes: Vec<String>) -> Self {
+        let (result_tx, result_rx) = mpsc::channel::<SignatureResult>();
+
+        let thread = thread::spawn(move || {
+            let repository = Repo::new(fallback_encoding, Vec::new())?;
+
+            for hash in hashes {
+                let status = repository.verify_signature(hash.as_str(), &verifier);
+
+                if result_tx.send(SignatureResult { hash, status }).is_err() {
+                    break;
+                }
+            }
+
+            Ok(())
+        });
+
+        SignatureWorker { results: result_rx, thread: Some(thread) }
+    }
+
+    pub fn try_recv(&mut self) -> Result<Option<SignatureResult>, Disconnected> {
+        receive(&self.results, &mut self.thread)
+    }
+}
+
+impl FilterWorker {
+    pub fn spawn(
+        fallback_encoding: &'static Encoding,
+        pathspecs: Vec<String>,
+        diff_settings: DiffSettings,
+        filter: &CommitFilter,
+        hashes: Vec<String>,
+    ) -> Self {
+        let (result_tx, result_rx) = mpsc::channel::<FilterResult>();
+        let (paths, pickaxes) = (filter.paths(), filter.pickaxes());
+
+        let thread = thread::spawn(move || {
+            let mut repository = Repo::new(fallback_encoding, pathspecs)?;
+            repository.set_diff_settings(diff_settings);
+
+            for hash in hashes {
+                let matched = repository.commit_matches(hash.as_str(), &paths, &pickaxes);
+
+                if result_tx.send(FilterResult { hash, matched }).is_err() {
+                    break;
+                }
+            }
+
+            Ok(())
+        });
+
+        FilterWorker { results: result_rx, thread: Some(thread) }
+    }
+
+    pub fn try_recv(&mut self) -> Result<Option<FilterResult>, Disconnected> {
+        receive(&self.results, &mut self.thread)
+    }
+}
+
+impl PathspecWorker {
+    pub fn spawn(fallback_encoding: &'static Encoding, pathspecs: Vec<String>, hashes: Vec<String>) -> Self {
+        let (result_tx, result_rx) = mpsc::channel::<PathspecResult>();
+
+        let thread = thread::spawn(move || {
+            let repository = Repo::new(fallback_encoding, pathspecs)?;
+
+            for hash in hashes {
+                let touched = repository.touches_pathspecs(hash.as_str());
+
+                if result_tx.send(PathspecResult { hash, touched }).is_err() {
+                    break;
+                }
+            }
+
+            Ok(())
+        });
+
+        PathspecWorker { results: result_rx, thread: Some(thread) }
+    }
+
+    pub fn try_recv(&mut self) -> Result<Option<PathspecResult>, Disconnected> {
+        receive(&self.results, &mut self.thread)
+    }
+}
+
+fn receive<T>(results: &Receiver<T>, thread: &mut Option<JoinHandle<Result<(), RepoError>>>) -> Result<Option<T>, Disconnected> {
+    match results.try_recv() {
+        Ok(result) => Ok(Some(result)),
+        Err(TryRecvError::Empty) => Ok(None),
+        Err(TryRecvError::Disconnected) => {
+            // the reason is only there the first time, after which the thread has been joined
+            let reason = match thread.take().map(JoinHandle::join) {
+                Some(Ok(Err(e))) => e.to_string(),
+                Some(Err(_)) => String::from("the worker thread panicked"),
+                Some(Ok(Ok(()))) | None => String::from("the worker thread has stopped"),
+            };
+
+            Err(Disconnected(reason))
+        },
+    }
+}